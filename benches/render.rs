@@ -0,0 +1,101 @@
+//! Rendering benchmark: per-pixel geometry vs. a shared lookup table
+//!
+//! Run with `cargo bench --bench render`.
+
+use std::f64::consts::PI;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use image::{Rgba, RgbaImage};
+use radar_plotter::{render_png, render_with_lut, CMap, PolarGrid, PolarLut, RenderOptions};
+
+const SIZE: u32 = 1735;
+const PULSES: usize = 720;
+const N_BINS: usize = 512;
+const ITERATIONS: u32 = 10;
+
+/// Deterministic grid with a mix of echoes, zeros and missing pulses
+fn synthetic_grid() -> PolarGrid {
+    let mut state = 0x2545_f491_u32;
+    let bins = (0..PULSES)
+        .map(|p| {
+            (0..N_BINS)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    if p % 97 == 0 { f32::NAN } else { (state % 256) as f32 }
+                })
+                .collect()
+        })
+        .collect();
+    let theta_edges = (0..=PULSES)
+        .map(|p| p as f64 * std::f64::consts::TAU / PULSES as f64)
+        .collect();
+    PolarGrid { theta_edges, bins }
+}
+
+/// The previous renderer: `sqrt`, `atan2` and a colormap evaluation per pixel
+fn render_per_pixel(grid: &PolarGrid, size: u32, cmap: &CMap) -> RgbaImage {
+    let pulses = grid.pulses();
+    let n_bins = grid.n_bins();
+    let max_val = grid.bins.iter().flatten().copied().filter(|v| v.is_finite()).fold(0.0f32, f32::max);
+    let mut img = RgbaImage::new(size, size);
+    let c = size as f64 / 2.0;
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let dx = x as f64 + 0.5 - c;
+        let dy = c - (y as f64 + 0.5);
+        let r_norm = (dx * dx + dy * dy).sqrt() / c;
+        if r_norm > 1.0 {
+            continue;
+        }
+        let theta = dx.atan2(dy).rem_euclid(2.0 * PI);
+        let pulse_idx = ((theta / (2.0 * PI)) * pulses as f64).floor() as usize % pulses;
+        let bin_idx = (r_norm * n_bins as f64).floor() as usize;
+        let Some(&v) = grid.bins[pulse_idx].get(bin_idx) else {
+            continue;
+        };
+        if v.is_finite() && v != 0.0 {
+            *pixel = Rgba(cmap.eval((v / max_val) as f64));
+        }
+    }
+    img
+}
+
+fn time<F: FnMut()>(label: &str, mut f: F) -> Duration {
+    f(); // warm up
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let per_iter = start.elapsed() / ITERATIONS;
+    println!("{label:<32} {:>8.2} ms/image", per_iter.as_secs_f64() * 1000.0);
+    per_iter
+}
+
+fn main() {
+    let grid = synthetic_grid();
+    let opts = RenderOptions { size: SIZE, ..RenderOptions::default() };
+    println!("{SIZE}px, {PULSES} pulses x {N_BINS} bins, {ITERATIONS} iterations");
+
+    let baseline = time("per-pixel geometry (previous)", || {
+        black_box(render_per_pixel(&grid, SIZE, &opts.cmap));
+    });
+    let lut_build = time("build lookup table", || {
+        black_box(PolarLut::new(SIZE, PULSES, N_BINS).unwrap());
+    });
+    let per_image = time("render_png (table per image)", || {
+        black_box(render_png(&grid, &opts).unwrap());
+    });
+    let lut = PolarLut::new(SIZE, PULSES, N_BINS).unwrap();
+    let shared = time("render_with_lut (shared table)", || {
+        black_box(render_with_lut(&grid, &lut, &opts).unwrap());
+    });
+
+    println!(
+        "speedup {:.1}x over per-pixel geometry, {:.1}x over a table per image (build {:.0} ms)",
+        baseline.as_secs_f64() / shared.as_secs_f64(),
+        per_image.as_secs_f64() / shared.as_secs_f64(),
+        lut_build.as_secs_f64() * 1000.0
+    );
+}
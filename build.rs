@@ -1,3 +1,5 @@
 fn main() {
+    // The UI is only compiled into the desktop application
+    #[cfg(feature = "gui")]
     slint_build::compile("ui/appwindow.slint").unwrap();
 }
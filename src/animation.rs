@@ -0,0 +1,216 @@
+//! Animated GIF / APNG output of rendered sweeps
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{anyhow, Context, Error, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame};
+
+use crate::timestamp::Timestamp;
+
+/// Delay used when filename timestamps cannot be parsed
+pub const DEFAULT_FRAME_DELAY_MS: u32 = 100;
+
+/// Shortest frame delay; most viewers slow down anything faster
+const MIN_FRAME_DELAY_MS: u32 = 20;
+
+/// Longest frame delay, so pauses in a capture don't stall playback
+const MAX_FRAME_DELAY_MS: u32 = 60_000;
+
+/// Container format for animated output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl FromStr for AnimationFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gif" => Ok(Self::Gif),
+            "apng" | "png" => Ok(Self::Apng),
+            _ => Err(anyhow!("Unknown animation format: {s}")),
+        }
+    }
+}
+
+impl AnimationFormat {
+    /// File extension without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Apng => "png",
+        }
+    }
+}
+
+/// How long each frame is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDelay {
+    /// The same delay in milliseconds for every frame
+    Fixed(u32),
+    /// Real-time playback from the timestamps in the CSV filenames
+    Timestamps,
+}
+
+/// Animated output written by [`process_folders`](crate::processing::process_folders).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationSettings {
+    pub format: AnimationFormat,
+    pub delay: FrameDelay,
+}
+
+/// Frame delays in milliseconds for frames rendered from the given CSV stems.
+///
+/// With [`FrameDelay::Timestamps`] each frame lasts until the next capture;
+/// frames sharing a stem, such as the rotations of one file, split that time
+/// evenly. If any stem has no parseable timestamp, [`DEFAULT_FRAME_DELAY_MS`]
+/// is used for every frame.
+pub fn frame_delays(stems: &[&str], delay: FrameDelay) -> Vec<u32> {
+    let fixed = match delay {
+        FrameDelay::Fixed(ms) => Some(ms),
+        FrameDelay::Timestamps => None,
+    };
+    let times: Option<Vec<f64>> = stems
+        .iter()
+        .map(|s| Timestamp::parse_filename(s).map(|t| t.unix_seconds()))
+        .collect();
+
+    match (fixed, times) {
+        (None, Some(times)) if times.len() > 1 => {
+            let mut delays: Vec<u32> = Vec::with_capacity(times.len());
+            let mut start = 0;
+            while start < times.len() {
+                let count = times[start..].iter().take_while(|&&t| t == times[start]).count();
+                let delay = match times.get(start + count) {
+                    Some(next) => (((next - times[start]) * 1000.0 / count as f64).round().max(0.0) as u32)
+                        .clamp(MIN_FRAME_DELAY_MS, MAX_FRAME_DELAY_MS),
+                    // The last capture lasts as long as the one before it
+                    None => delays.last().copied().unwrap_or(DEFAULT_FRAME_DELAY_MS),
+                };
+                delays.extend(std::iter::repeat_n(delay, count));
+                start += count;
+            }
+            delays
+        }
+        (Some(ms), _) => vec![ms.clamp(MIN_FRAME_DELAY_MS, MAX_FRAME_DELAY_MS); stems.len()],
+        _ => vec![DEFAULT_FRAME_DELAY_MS; stems.len()],
+    }
+}
+
+/// Write the PNG `frames` as one animation, reading them one at a time.
+///
+/// All frames must have the size of the first. Setting `stop_flag` aborts
+/// the write and removes the partial file.
+pub fn write_animation(
+    frames: &[PathBuf],
+    delays_ms: &[u32],
+    format: AnimationFormat,
+    output: &Path,
+    stop_flag: &AtomicBool,
+) -> Result<()> {
+    if frames.is_empty() {
+        return Err(anyhow!("no frames to animate"));
+    }
+    let result = match format {
+        AnimationFormat::Gif => write_gif(frames, delays_ms, output, stop_flag),
+        AnimationFormat::Apng => write_apng(frames, delays_ms, output, stop_flag),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result.with_context(|| format!("writing {}", output.display()))
+}
+
+fn load_frame(path: &Path) -> Result<image::RgbaImage> {
+    Ok(image::open(path)
+        .with_context(|| format!("reading {}", path.display()))?
+        .to_rgba8())
+}
+
+fn write_gif(frames: &[PathBuf], delays_ms: &[u32], output: &Path, stop_flag: &AtomicBool) -> Result<()> {
+    let file = BufWriter::new(File::create(output)?);
+    let mut encoder = GifEncoder::new_with_speed(file, 10);
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    for (i, path) in frames.iter().enumerate() {
+        if stop_flag.load(Ordering::Relaxed) {
+            return Err(anyhow!("cancelled"));
+        }
+        let img = load_frame(path)?;
+        let ms = delays_ms.get(i).copied().unwrap_or(DEFAULT_FRAME_DELAY_MS);
+        encoder.encode_frame(Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(ms, 1)))?;
+    }
+    Ok(())
+}
+
+fn write_apng(frames: &[PathBuf], delays_ms: &[u32], output: &Path, stop_flag: &AtomicBool) -> Result<()> {
+    let first = load_frame(&frames[0])?;
+    let (width, height) = first.dimensions();
+
+    let file = BufWriter::new(File::create(output)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+
+    let mut img = first;
+    for (i, path) in frames.iter().enumerate() {
+        if stop_flag.load(Ordering::Relaxed) {
+            return Err(anyhow!("cancelled"));
+        }
+        if i > 0 {
+            img = load_frame(path)?;
+        }
+        if img.dimensions() != (width, height) {
+            return Err(anyhow!("frame size mismatch in {}", path.display()));
+        }
+        let ms = delays_ms.get(i).copied().unwrap_or(DEFAULT_FRAME_DELAY_MS);
+        writer.set_frame_delay(ms.min(u16::MAX as u32) as u16, 1000)?;
+        writer.write_image_data(img.as_raw())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_follow_timestamps() {
+        let stems = ["20240101_120000", "20240101_120001", "20240101_120003"];
+        // The last frame lasts as long as the one before it
+        assert_eq!(frame_delays(&stems, FrameDelay::Timestamps), [1000, 2000, 2000]);
+    }
+
+    #[test]
+    fn frames_of_one_file_share_its_time() {
+        let stems = ["20240101_120000", "20240101_120000", "20240101_120000", "20240101_120003"];
+        assert_eq!(frame_delays(&stems, FrameDelay::Timestamps), [1000, 1000, 1000, 1000]);
+    }
+
+    #[test]
+    fn delays_are_clamped() {
+        let stems = ["20240101120000000", "20240101120000005", "20240101140000005"];
+        assert_eq!(
+            frame_delays(&stems, FrameDelay::Timestamps),
+            [MIN_FRAME_DELAY_MS, MAX_FRAME_DELAY_MS, MAX_FRAME_DELAY_MS]
+        );
+        assert_eq!(frame_delays(&stems[..2], FrameDelay::Fixed(5)), [MIN_FRAME_DELAY_MS; 2]);
+    }
+
+    #[test]
+    fn unparseable_stems_use_default_delay() {
+        let stems = ["20240101_120000", "capture"];
+        assert_eq!(frame_delays(&stems, FrameDelay::Timestamps), [DEFAULT_FRAME_DELAY_MS; 2]);
+        assert_eq!(frame_delays(&stems, FrameDelay::Fixed(250)), [250, 250]);
+    }
+}
@@ -0,0 +1,531 @@
+//! CSV to PPI CLI - Headless Entry Point
+//!
+//! Batch converts Furuno CSV radar data to PPI images without a display.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use clap::{Args as ClapArgs, Parser};
+use radar_plotter::animation::{AnimationFormat, AnimationSettings, FrameDelay};
+use radar_plotter::geo::GeoReference;
+use radar_plotter::grid::{Aggregation, GapFill};
+use radar_plotter::heading::{HeadingSettings, HeadingSource, Orientation};
+use radar_plotter::manifest::ResumeMode;
+use radar_plotter::legend::{LegendOptions, LegendPlacement};
+use radar_plotter::overlay::{self, OverlayMode, OverlayOptions};
+use radar_plotter::render::{AlphaRamp, Interpolation, Transfer};
+use radar_plotter::sweep::{self, Column, CsvFormat, DEFAULT_TICKS_PER_REV};
+use radar_plotter::validation::{self, ValidationMode};
+use radar_plotter::watch::WatchSettings;
+use radar_plotter::{processing, queue, CMap, Normalization};
+
+#[derive(Parser, Debug)]
+#[command(name = "radar_plotter_cli", version, about = "Batch convert radar CSV captures to PPI images")]
+struct Args {
+    /// Folders containing CSV files to process
+    #[arg(required = true)]
+    folders: Vec<PathBuf>,
+
+    /// Pulses per revolution for resampling
+    #[arg(short, long, default_value_t = 720)]
+    pulses: usize,
+
+    /// Max gap (degrees) to interpolate
+    #[arg(long, default_value_t = 1.0)]
+    gap_deg: f64,
+
+    /// Filling of gaps up to --gap-deg: none, nearest, linear or cubic
+    #[arg(long, value_name = "METHOD", default_value = "linear")]
+    gap_fill: GapFill,
+
+    /// Mounting correction in degrees, added to every pulse azimuth
+    #[arg(long, value_name = "DEG", default_value_t = 0.0, allow_negative_numbers = true)]
+    azimuth_offset: f64,
+
+    /// Combining of pulses that fall into the same step: last, mean, max or nearest
+    #[arg(long, value_name = "MODE", default_value = "last")]
+    aggregate: Aggregation,
+
+    /// Output image dimensions (square)
+    #[arg(long, default_value_t = 1735)]
+    size: u32,
+
+    /// Pixel sampling: nearest (fast) or bilinear (smooth, for publication figures)
+    #[arg(long, value_name = "METHOD", default_value = "nearest")]
+    interpolation: Interpolation,
+
+    /// Anti-alias the disk edge and edges of missing data with NxN subsamples per pixel (1 = off)
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8))]
+    antialias: u32,
+
+    /// Color scheme: viridis, turbo, magma, gray, a colormap
+    /// file (.csv or .json) or the name of one in the user colormap folder
+    #[arg(long, default_value = "viridis", value_parser = parse_cmap)]
+    cmap: CMap,
+
+    /// Intensity normalization: image, folder, fixed[:MIN:MAX], percentile[:P]
+    #[arg(long, default_value = "image")]
+    norm: Normalization,
+
+    /// Intensity curve onto the colormap: linear, log or db[:RANGE_DB] (default 30), gamma[:G] (default 0.5)
+    #[arg(long, value_name = "CURVE", default_value = "linear")]
+    transfer: Transfer,
+
+    /// Echoes below this value are transparent and the colormap starts here
+    #[arg(long, value_name = "VALUE")]
+    noise_floor: Option<f32>,
+
+    /// Echoes above this value get the top color and the colormap ends here
+    #[arg(long, value_name = "VALUE")]
+    ceiling: Option<f32>,
+
+    /// Opacity by intensity: opaque, or ramp[:START:END[:GAMMA]] from transparent at START to opaque at END (colormap fractions, default 0:1:1)
+    #[arg(long, value_name = "CURVE", default_value = "opaque")]
+    alpha: AlphaRamp,
+
+    /// Also write an animation of each folder: gif or apng
+    #[arg(long, value_name = "FORMAT")]
+    animate: Option<AnimationFormat>,
+
+    /// Animation frame delay in ms (default: from filename timestamps)
+    #[arg(long, value_name = "MS", requires = "animate")]
+    frame_delay: Option<u32>,
+
+    /// Skip files rendered by an earlier run: off, newer (output newer than
+    /// input) or manifest (same settings and input hash)
+    #[arg(long, value_name = "MODE", default_value = "off")]
+    resume: ResumeMode,
+
+    /// Keep running and render new CSV files as they are written
+    #[arg(long)]
+    watch: bool,
+
+    /// Seconds a new file must stay unchanged before it is rendered
+    #[arg(long, value_name = "SECS", default_value = "2", requires = "watch", value_parser = parse_seconds)]
+    settle: Duration,
+
+    /// Thread count (0 = 90% of cores)
+    #[arg(short, long, default_value_t = 0)]
+    jobs: usize,
+
+    #[command(flatten)]
+    csv: CsvArgs,
+
+    #[command(flatten)]
+    heading: HeadingArgs,
+
+    #[command(flatten)]
+    overlay: OverlayArgs,
+
+    #[command(flatten)]
+    legend: LegendArgs,
+
+    #[command(flatten)]
+    geo: GeoArgs,
+}
+
+#[derive(ClapArgs, Debug)]
+#[command(next_help_heading = "CSV columns")]
+struct CsvArgs {
+    /// Field delimiter: a single character, tab or space
+    #[arg(long, default_value = ",", value_parser = parse_delimiter)]
+    delimiter: char,
+
+    /// The files have no header line; columns must be given by index
+    #[arg(long)]
+    no_header: bool,
+
+    /// Range setting column, by header name or zero-based index
+    #[arg(long, value_name = "COLUMN", default_value = "Range")]
+    range_column: Column,
+
+    /// Gain column
+    #[arg(long, value_name = "COLUMN", default_value = "Gain")]
+    gain_column: Column,
+
+    /// Encoder angle column
+    #[arg(long, value_name = "COLUMN", default_value = "Angle")]
+    angle_column: Column,
+
+    /// Encoder ticks per revolution of the angle column
+    #[arg(long, value_name = "TICKS", default_value_t = DEFAULT_TICKS_PER_REV, value_parser = clap::value_parser!(u32).range(2..))]
+    ticks_per_rev: u32,
+
+    /// First echo value column; echoes run to the end of each row
+    #[arg(long, value_name = "COLUMN", default_value = "EchoValues")]
+    echo_column: Column,
+
+    /// Vessel heading column in degrees, for north-up or head-up images
+    #[arg(long, value_name = "COLUMN", conflicts_with_all = ["vessel_heading", "heading_file"])]
+    heading_column: Option<Column>,
+
+    /// Drop rows whose status is not 1, reading the status from COLUMN
+    #[arg(long, value_name = "COLUMN", num_args = 0..=1, default_missing_value = "Status")]
+    status_column: Option<Column>,
+
+    /// Scale the range by COLUMN, the number of bins spanning the range setting
+    #[arg(long, value_name = "COLUMN", num_args = 0..=1, default_missing_value = "Scale")]
+    scale_column: Option<Column>,
+
+    /// Render one image per antenna rotation of files spanning several,
+    /// numbered _r001, _r002, ...
+    #[arg(long)]
+    split_rotations: bool,
+
+    /// Check for malformed rows: off, warn (render and report) or strict
+    /// (fail the file); reports go to validation_report.txt
+    #[arg(long, value_name = "MODE", default_value = "off")]
+    validate: ValidationMode,
+}
+
+impl CsvArgs {
+    fn format(&self) -> CsvFormat {
+        CsvFormat {
+            delimiter: self.delimiter,
+            header: !self.no_header,
+            range: self.range_column.clone(),
+            gain: self.gain_column.clone(),
+            angle: self.angle_column.clone(),
+            echoes: self.echo_column.clone(),
+            heading: self.heading_column.clone(),
+            status: self.status_column.clone(),
+            scale: self.scale_column.clone(),
+            ticks_per_rev: self.ticks_per_rev,
+        }
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+#[command(next_help_heading = "Vessel heading")]
+struct HeadingArgs {
+    /// Constant vessel heading in degrees
+    #[arg(long, value_name = "DEG", allow_negative_numbers = true, conflicts_with = "heading_file")]
+    vessel_heading: Option<f64>,
+
+    /// File of time,heading lines (e.g. from NMEA HDT), matched by the
+    /// capture filename timestamps
+    #[arg(long, value_name = "FILE")]
+    heading_file: Option<PathBuf>,
+
+    /// Image orientation when a heading is given: north-up or head-up
+    #[arg(long, default_value = "north-up")]
+    orientation: Orientation,
+}
+
+impl HeadingArgs {
+    fn settings(&self, heading_column: bool) -> Option<HeadingSettings> {
+        let source = match (self.vessel_heading, &self.heading_file) {
+            (Some(deg), _) => HeadingSource::Constant(deg),
+            (None, Some(path)) => HeadingSource::File(path.clone()),
+            (None, None) if heading_column => HeadingSource::Column,
+            (None, None) => return None,
+        };
+        Some(HeadingSettings {
+            source,
+            orientation: self.orientation,
+        })
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+#[command(next_help_heading = "Overlay")]
+struct OverlayArgs {
+    /// Draw range rings and bearings: burn (into each image) or separate (overlay_<range>.png)
+    #[arg(long, value_name = "MODE")]
+    overlay: Option<OverlayMode>,
+
+    /// Number of range rings
+    #[arg(long, default_value_t = 4)]
+    rings: u32,
+
+    /// Degrees between bearing spokes, 1-180 (0 = none)
+    #[arg(long, default_value_t = 30.0, value_parser = parse_spoke_deg)]
+    spoke_deg: f64,
+
+    /// Ring and spoke color
+    #[arg(long, default_value = "ffffff", value_parser = parse_color)]
+    overlay_color: [u8; 3],
+
+    /// Label color
+    #[arg(long, default_value = "ffffff", value_parser = parse_color)]
+    label_color: [u8; 3],
+
+    /// Overlay opacity (0-1)
+    #[arg(long, default_value_t = 0.6, value_parser = parse_opacity)]
+    overlay_opacity: f32,
+
+    /// Omit the north marker
+    #[arg(long)]
+    no_north_marker: bool,
+
+    /// Omit range and bearing labels
+    #[arg(long)]
+    no_labels: bool,
+}
+
+impl OverlayArgs {
+    fn options(&self) -> Option<OverlayOptions> {
+        Some(OverlayOptions {
+            rings: self.rings,
+            spoke_deg: self.spoke_deg,
+            north_marker: !self.no_north_marker,
+            labels: !self.no_labels,
+            line_color: self.overlay_color,
+            label_color: self.label_color,
+            opacity: self.overlay_opacity,
+            mode: self.overlay?,
+            ..OverlayOptions::default()
+        })
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+#[command(next_help_heading = "Legend")]
+struct LegendArgs {
+    /// Add a colorbar and caption: extend (below the disk) or corner
+    #[arg(long, value_name = "PLACEMENT")]
+    legend: Option<LegendPlacement>,
+
+    /// Omit the colorbar
+    #[arg(long)]
+    no_colorbar: bool,
+
+    /// Omit the timestamp, gain and range caption
+    #[arg(long)]
+    no_caption: bool,
+
+    /// Legend text color
+    #[arg(long, default_value = "ffffff", value_parser = parse_color)]
+    legend_color: [u8; 3],
+}
+
+impl LegendArgs {
+    fn options(&self) -> Option<LegendOptions> {
+        Some(LegendOptions {
+            placement: self.legend?,
+            colorbar: !self.no_colorbar,
+            caption: !self.no_caption,
+            text_color: self.legend_color,
+        })
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+#[command(next_help_heading = "Georeference")]
+struct GeoArgs {
+    /// Radar latitude in degrees; writes .pgw/.prj files next to each image
+    #[arg(long, allow_negative_numbers = true, requires_all = ["lon", "meters_per_unit"])]
+    lat: Option<f64>,
+
+    /// Radar longitude in degrees
+    #[arg(long, allow_negative_numbers = true, requires = "lat")]
+    lon: Option<f64>,
+
+    /// Direction of the top of the image, degrees clockwise from north
+    #[arg(long, value_name = "DEG", default_value_t = 0.0, allow_negative_numbers = true)]
+    radar_heading: f64,
+
+    /// Meters per range unit (e.g. 1852 for nautical miles)
+    #[arg(long, value_name = "M", requires = "lat")]
+    meters_per_unit: Option<f64>,
+
+    /// Also write a KMZ of each folder for Google Earth
+    #[arg(long, requires = "lat")]
+    kmz: bool,
+}
+
+impl GeoArgs {
+    fn reference(&self) -> Option<GeoReference> {
+        Some(GeoReference {
+            lat: self.lat?,
+            lon: self.lon?,
+            heading_deg: self.radar_heading,
+            meters_per_unit: self.meters_per_unit?,
+        })
+    }
+}
+
+fn parse_cmap(s: &str) -> Result<CMap, String> {
+    CMap::find(s).map_err(|e| format!("{e:#}"))
+}
+
+fn parse_delimiter(s: &str) -> Result<char, String> {
+    sweep::parse_delimiter(s).map_err(|e| e.to_string())
+}
+
+fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    overlay::parse_hex_color(s).map_err(|e| e.to_string())
+}
+
+fn parse_spoke_deg(s: &str) -> Result<f64, String> {
+    let deg: f64 = s.parse().map_err(|_| format!("invalid number: {s}"))?;
+    if deg == 0.0 || overlay::SPOKE_DEG_RANGE.contains(&deg) {
+        Ok(deg)
+    } else {
+        Err("must be 0 or between 1 and 180".to_string())
+    }
+}
+
+fn parse_opacity(s: &str) -> Result<f32, String> {
+    let opacity: f32 = s.parse().map_err(|_| format!("invalid number: {s}"))?;
+    if (0.0..=1.0).contains(&opacity) {
+        Ok(opacity)
+    } else {
+        Err("must be between 0 and 1".to_string())
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s.parse().map_err(|_| format!("invalid number: {s}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|_| "must be a non-negative number of seconds".to_string())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let mut folders = Vec::new();
+    for path in &args.folders {
+        if !path.is_dir() {
+            eprintln!("error: {} is not a directory", path.display());
+            return ExitCode::FAILURE;
+        }
+        let name = path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("Unknown")
+            .to_string();
+        folders.push(queue::FolderInfo {
+            path: path.clone(),
+            name,
+            file_count: queue::count_csv_files(path),
+            status: queue::FolderStatus::Pending,
+            progress: 0.0,
+            error_message: None,
+        });
+    }
+
+    let settings = processing::ProcessingSettings {
+        pulses: args.pulses,
+        gap_deg: args.gap_deg,
+        gap_fill: args.gap_fill,
+        azimuth_offset_deg: args.azimuth_offset,
+        aggregation: args.aggregate,
+        heading: args.heading.settings(args.csv.heading_column.is_some()),
+        csv_format: args.csv.format(),
+        split_rotations: args.csv.split_rotations,
+        validation: args.csv.validate,
+        size: args.size,
+        interpolation: args.interpolation,
+        antialias: args.antialias,
+        colormap: args.cmap,
+        normalization: args.norm,
+        transfer: args.transfer,
+        noise_floor: args.noise_floor,
+        ceiling: args.ceiling,
+        alpha: args.alpha,
+        animation: args.animate.map(|format| AnimationSettings {
+            format,
+            delay: args.frame_delay.map_or(FrameDelay::Timestamps, FrameDelay::Fixed),
+        }),
+        overlay: args.overlay.options(),
+        legend: args.legend.options(),
+        georef: args.geo.reference(),
+        kmz: args.geo.kmz,
+        resume: args.resume,
+        watch: args.watch.then(|| WatchSettings {
+            settle: args.settle,
+            ..WatchSettings::default()
+        }),
+        preview: false,
+        jobs: args.jobs,
+    };
+
+    let folders_total = folders.len();
+    let folder_names: Vec<String> = folders.iter().map(|f| f.name.clone()).collect();
+    let (tx, rx) = mpsc::channel::<processing::ProgressUpdate>();
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    // Ctrl-C or SIGTERM lets the files in progress finish; a second one quits at once
+    let stop = stop_flag.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if stop.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+    }) {
+        eprintln!("warning: cannot handle Ctrl-C: {}", e);
+    }
+    let handle = thread::spawn(move || {
+        processing::process_folders(folders, settings, tx, stop_flag);
+    });
+
+    let mut failed = 0usize;
+    for update in rx {
+        match update {
+            processing::ProgressUpdate::FolderStarted { folder_index, folder_name } => {
+                println!("[{}/{}] {}", folder_index + 1, folders_total, folder_name);
+            }
+            processing::ProgressUpdate::FileProgress {
+                files_done,
+                files_total,
+                current_file,
+                files_per_second,
+                ..
+            } => {
+                eprint!(
+                    "\r  {}/{} files  {:.1} files/s  {}\x1b[K",
+                    files_done, files_total, files_per_second, current_file
+                );
+                let _ = std::io::stderr().flush();
+            }
+            processing::ProgressUpdate::FolderCompleted { files_skipped, files_flagged, .. } => {
+                eprintln!();
+                let mut notes = Vec::new();
+                if files_skipped > 0 {
+                    notes.push(format!("{} up to date", files_skipped));
+                }
+                if files_flagged > 0 {
+                    notes.push(format!("{} flagged in {}", files_flagged, validation::REPORT_NAME));
+                }
+                if notes.is_empty() {
+                    println!("  complete");
+                } else {
+                    println!("  complete ({})", notes.join(", "));
+                }
+            }
+            processing::ProgressUpdate::FolderError { error, .. } => {
+                eprintln!();
+                eprintln!("  error: {}", error);
+                failed += 1;
+            }
+            processing::ProgressUpdate::FolderWatching { .. } => {
+                eprintln!();
+                println!("  watching for new files");
+            }
+            processing::ProgressUpdate::FileWatched { folder_index, file_name, error } => {
+                let folder = &folder_names[folder_index];
+                match error {
+                    None => println!("  {}: {}", folder, file_name),
+                    Some(error) => eprintln!("  {}: {}: error: {}", folder, file_name, error),
+                }
+            }
+            processing::ProgressUpdate::Preview { .. } => {}
+            processing::ProgressUpdate::AllComplete => {
+                println!("Processing complete: {} of {} folders succeeded", folders_total - failed, folders_total);
+            }
+            processing::ProgressUpdate::Cancelled => {
+                println!("Cancelled");
+            }
+        }
+    }
+
+    let _ = handle.join();
+
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
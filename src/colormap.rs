@@ -0,0 +1,560 @@
+//! Colormaps for mapping normalized intensity to color
+//!
+//! Besides the built-in maps, colormaps can be loaded from files, either a
+//! CSV of color stops or a matplotlib-style JSON (see [`CustomCMap::parse_csv`]
+//! and [`CustomCMap::parse_json`]). Files in [`user_dir`] are found by name,
+//! so they can be picked like the built-in maps.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Error, Result};
+use colorous::{Color, Gradient, MAGMA, TURBO, VIRIDIS};
+use serde_json::Value;
+
+/// Number of entries in a precomputed colormap table
+pub const TABLE_SIZE: usize = 4096;
+
+/// Built-in and user colormaps.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CMap {
+    Viridis,
+    Turbo,
+    Magma,
+    Gray,
+    /// Loaded from a colormap file
+    Custom(Arc<CustomCMap>),
+}
+
+impl FromStr for CMap {
+    type Err = Error;
+
+    /// Parse a built-in colormap name (case-insensitive).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "viridis" => Ok(Self::Viridis),
+            "turbo" => Ok(Self::Turbo),
+            "magma" => Ok(Self::Magma),
+            "gray" | "grey" | "grayscale" => Ok(Self::Gray),
+            _ => Err(anyhow!("Unknown colormap: {s}")),
+        }
+    }
+}
+
+impl CMap {
+    /// Colormap by name: a built-in map, a colormap file path, or the file
+    /// stem of a colormap in [`user_dir`].
+    pub fn find(name: &str) -> Result<Self> {
+        if let Ok(cmap) = name.parse() {
+            return Ok(cmap);
+        }
+        let path = Path::new(name);
+        if path.is_file() {
+            return CustomCMap::load(path).map(|c| Self::Custom(Arc::new(c)));
+        }
+        let found = user_dir().and_then(|dir| {
+            colormap_files(&dir)
+                .into_iter()
+                .find(|p| p.file_stem().is_some_and(|stem| stem.to_string_lossy().eq_ignore_ascii_case(name)))
+        });
+        match found {
+            Some(path) => CustomCMap::load(&path).map(|c| Self::Custom(Arc::new(c))),
+            None => Err(anyhow!("Unknown colormap: {name}")),
+        }
+    }
+
+    /// Name the colormap is selected and saved by.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Viridis => "viridis",
+            Self::Turbo => "turbo",
+            Self::Magma => "magma",
+            Self::Gray => "gray",
+            Self::Custom(custom) => &custom.name,
+        }
+    }
+
+    /// Map a normalized value in `[0, 1]` to RGBA. Values outside are clamped.
+    pub fn eval(&self, v: f64) -> [u8; 4] {
+        let v = v.clamp(0.0, 1.0);
+        match self {
+            Self::Viridis => to_rgba(VIRIDIS, v),
+            Self::Turbo => to_rgba(TURBO, v),
+            Self::Magma => to_rgba(MAGMA, v),
+            Self::Gray => {
+                let g = (v * 255.0).round() as u8;
+                [g, g, g, 255]
+            }
+            Self::Custom(custom) => custom.eval(v),
+        }
+    }
+}
+
+/// Colormap built from color stops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomCMap {
+    /// File stem of the colormap file
+    pub name: String,
+    /// `(position, [r, g, b, a])` sorted by position in `[0, 1]`, channels
+    /// in `[0, 1]`; two stops at one position make a hard edge
+    stops: Vec<(f64, [f64; 4])>,
+    /// Each stop's color holds up to the next stop instead of blending
+    pub discrete: bool,
+}
+
+/// A stop as read from a file, before scaling.
+struct RawStop {
+    /// Position in file units, e.g. a dBZ level
+    position: Option<f64>,
+    /// `[r, g, b, a]`, alpha missing when not given
+    channels: [Option<f64>; 4],
+    /// Channels are already in `[0, 1]`, as for hex colors
+    fractional: bool,
+}
+
+impl RawStop {
+    fn hex(position: Option<f64>, hex: &str) -> Option<Self> {
+        Some(RawStop { position, channels: parse_hex(hex)?.map(Some), fractional: true })
+    }
+}
+
+impl CustomCMap {
+    /// Read a `.csv` or `.json` colormap file, named after the file stem.
+    pub fn load(path: &Path) -> Result<Self> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let is_json = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+        let parsed = if is_json { Self::parse_json(name, &text) } else { Self::parse_csv(name, &text) };
+        parsed.with_context(|| format!("reading {}", path.display()))
+    }
+
+    /// Parse a CSV of color stops, one per line.
+    ///
+    /// A stop is `r,g,b` or `r,g,b,a`, or a hex color such as `#00ff00` or
+    /// `#00ff0080`. Channels are 0–255, or 0–1 when no value in the file
+    /// exceeds 1. Stops are evenly spaced unless a header line names a
+    /// `position` (or `value`) column next to `r,g,b[,a]` or `color`
+    /// columns, such as the dBZ level of each color; positions are scaled to
+    /// the colormap. Lines starting with `#` that are not hex colors are
+    /// comments, and the comment `# discrete` makes every color hold up to
+    /// the next stop; the last color then holds for as long as the one
+    /// before it.
+    pub fn parse_csv(name: String, text: &str) -> Result<Self> {
+        let mut discrete = false;
+        let mut header: Option<Vec<String>> = None;
+        let mut stops = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('#') && parse_hex(line).is_none() {
+                match line.trim_start_matches('#').trim().to_ascii_lowercase().as_str() {
+                    "discrete" => discrete = true,
+                    "continuous" => discrete = false,
+                    _ => {}
+                }
+                continue;
+            }
+            let fields: Vec<&str> = line.split([',', ';', '\t']).map(str::trim).collect();
+            let numeric = fields.iter().all(|f| f.parse::<f64>().is_ok() || parse_hex(f).is_some());
+            if !numeric && stops.is_empty() && header.is_none() {
+                header = Some(fields.iter().map(|f| f.to_ascii_lowercase()).collect());
+                continue;
+            }
+            let stop = csv_stop(&fields, header.as_deref())
+                .ok_or_else(|| anyhow!("line {}: expected a color", index + 1))?;
+            stops.push(stop);
+        }
+        Self::from_stops(name, stops, discrete)
+    }
+
+    /// Parse a matplotlib-style JSON colormap.
+    ///
+    /// Accepted are a list of colors like `ListedColormap.colors`, an object
+    /// with such a `colors` list and optional `positions` and
+    /// `"interpolation": "discrete"`, or segment data like
+    /// `LinearSegmentedColormap._segmentdata` with `red`, `green`, `blue` and
+    /// optional `alpha` lists of `[x, y0, y1]`. Colors are `[r, g, b(, a)]`
+    /// lists or hex strings.
+    pub fn parse_json(name: String, text: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(text)?;
+        if value.get("red").is_some() {
+            return Self::from_segments(name, &value);
+        }
+        let colors = value.get("colors").unwrap_or(&value);
+        let colors = colors.as_array().ok_or_else(|| anyhow!("expected a list of colors"))?;
+        let positions: Vec<Option<f64>> = match value.get("positions").or_else(|| value.get("values")) {
+            Some(list) => {
+                let list = list.as_array().ok_or_else(|| anyhow!("positions must be a list"))?;
+                if list.len() != colors.len() {
+                    return Err(anyhow!("{} positions for {} colors", list.len(), colors.len()));
+                }
+                list.iter()
+                    .map(|p| p.as_f64().map(Some).ok_or_else(|| anyhow!("invalid position {p}")))
+                    .collect::<Result<_>>()?
+            }
+            None => vec![None; colors.len()],
+        };
+        let discrete = value.get("interpolation").and_then(Value::as_str) == Some("discrete");
+        let stops = colors
+            .iter()
+            .zip(positions)
+            .map(|(color, position)| json_stop(position, color).ok_or_else(|| anyhow!("invalid color {color}")))
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_stops(name, stops, discrete)
+    }
+
+    /// Scale channels and positions of stops read from a file to `[0, 1]`.
+    /// Missing alpha is opaque.
+    fn from_stops(name: String, raw: Vec<RawStop>, discrete: bool) -> Result<Self> {
+        if raw.is_empty() {
+            return Err(anyhow!("no colors"));
+        }
+        let max_channel = raw
+            .iter()
+            .filter(|s| !s.fractional)
+            .flat_map(|s| s.channels.iter().flatten())
+            .fold(0.0f64, |m, &v| m.max(v));
+        let unit = if max_channel > 1.0 { 255.0 } else { 1.0 };
+        let n = raw.len();
+        let positions: Vec<f64> = match raw.iter().map(|s| s.position).collect::<Option<Vec<f64>>>() {
+            Some(positions) => {
+                let (first, last) = (positions[0], positions[n - 1]);
+                if positions.windows(2).any(|w| w[1] < w[0]) || (n > 1 && last <= first) {
+                    return Err(anyhow!("positions must increase"));
+                }
+                // A discrete color starts its band, so the last one gets a
+                // band as wide as the one before it
+                let end = if discrete && n > 1 { last + (last - positions[n - 2]) } else { last };
+                positions.iter().map(|p| if n > 1 { (p - first) / (end - first) } else { 0.0 }).collect()
+            }
+            // Equal bands, so the last color gets as much room as the others
+            None if discrete => (0..n).map(|i| i as f64 / n as f64).collect(),
+            None => (0..n).map(|i| i as f64 / (n - 1).max(1) as f64).collect(),
+        };
+        let stops = positions
+            .into_iter()
+            .zip(raw)
+            .map(|(p, s)| {
+                let unit = if s.fractional { 1.0 } else { unit };
+                (p, s.channels.map(|v| (v.map_or(1.0, |v| v / unit)).clamp(0.0, 1.0)))
+            })
+            .collect();
+        Ok(CustomCMap { name, stops, discrete })
+    }
+
+    /// Build from matplotlib segment data.
+    fn from_segments(name: String, value: &Value) -> Result<Self> {
+        let channel = |key: &str| -> Result<Option<Vec<[f64; 3]>>> {
+            let Some(list) = value.get(key) else {
+                return Ok(None);
+            };
+            let points = list
+                .as_array()
+                .ok_or_else(|| anyhow!("{key} must be a list"))?
+                .iter()
+                .map(|p| {
+                    let numbers: Option<Vec<f64>> = p.as_array().and_then(|p| p.iter().map(Value::as_f64).collect());
+                    match numbers.as_deref() {
+                        Some(&[x, y0, y1]) => Ok([x, y0, y1]),
+                        _ => Err(anyhow!("{key}: expected [x, y0, y1], got {p}")),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if points.is_empty() || points.windows(2).any(|w| w[1][0] < w[0][0]) {
+                return Err(anyhow!("{key}: x must increase"));
+            }
+            Ok(Some(points))
+        };
+        let required = |key: &str| channel(key)?.ok_or_else(|| anyhow!("missing {key}"));
+        let channels = [
+            required("red")?,
+            required("green")?,
+            required("blue")?,
+            channel("alpha")?.unwrap_or_else(|| vec![[0.0, 1.0, 1.0], [1.0, 1.0, 1.0]]),
+        ];
+
+        let mut xs: Vec<f64> = channels.iter().flatten().map(|p| p[0].clamp(0.0, 1.0)).collect();
+        xs.sort_by(f64::total_cmp);
+        xs.dedup();
+        let mut stops = Vec::new();
+        for x in xs {
+            let left = [0, 1, 2, 3].map(|i| segment_value(&channels[i], x, false));
+            let right = [0, 1, 2, 3].map(|i| segment_value(&channels[i], x, true));
+            stops.push((x, left));
+            if right != left {
+                stops.push((x, right));
+            }
+        }
+        Ok(CustomCMap { name, stops, discrete: false })
+    }
+
+    fn eval(&self, v: f64) -> [u8; 4] {
+        let after = self.stops.partition_point(|&(p, _)| p <= v);
+        let color = match self.stops.get(after) {
+            _ if after == 0 => self.stops[0].1,
+            Some(&(p1, c1)) if !self.discrete => {
+                let (p0, c0) = self.stops[after - 1];
+                let t = (v - p0) / (p1 - p0);
+                [0, 1, 2, 3].map(|i| c0[i] + (c1[i] - c0[i]) * t)
+            }
+            _ => self.stops[after - 1].1,
+        };
+        color.map(|c| (c * 255.0).round() as u8)
+    }
+}
+
+/// One CSV stop, with columns named by `header` if there is one.
+fn csv_stop(fields: &[&str], header: Option<&[String]>) -> Option<RawStop> {
+    let number = |i: usize| fields.get(i).and_then(|f| f.parse::<f64>().ok());
+    let Some(header) = header else {
+        return match fields.len() {
+            1 => RawStop::hex(None, fields[0]),
+            3 | 4 => Some(RawStop {
+                position: None,
+                channels: [Some(number(0)?), Some(number(1)?), Some(number(2)?), number(3)],
+                fractional: false,
+            }),
+            _ => None,
+        };
+    };
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let position = match column(&["position", "pos", "value", "level", "x"]) {
+        Some(i) => Some(number(i)?),
+        None => None,
+    };
+    if let Some(i) = column(&["color", "colour", "hex"]) {
+        return RawStop::hex(position, fields.get(i)?);
+    }
+    let channel = |names: &[&str]| column(names).and_then(number);
+    Some(RawStop {
+        position,
+        channels: [
+            Some(channel(&["r", "red"])?),
+            Some(channel(&["g", "green"])?),
+            Some(channel(&["b", "blue"])?),
+            channel(&["a", "alpha"]),
+        ],
+        fractional: false,
+    })
+}
+
+/// A JSON color, `[r, g, b(, a)]` or a hex string.
+fn json_stop(position: Option<f64>, color: &Value) -> Option<RawStop> {
+    if let Some(hex) = color.as_str() {
+        return RawStop::hex(position, hex);
+    }
+    let channels: Vec<f64> = color.as_array()?.iter().map(Value::as_f64).collect::<Option<_>>()?;
+    let channels = match channels[..] {
+        [r, g, b] => [Some(r), Some(g), Some(b), None],
+        [r, g, b, a] => [Some(r), Some(g), Some(b), Some(a)],
+        _ => return None,
+    };
+    Some(RawStop { position, channels, fractional: false })
+}
+
+/// `#RRGGBB` or `#RRGGBBAA` as channels in `[0, 1]`.
+fn parse_hex(s: &str) -> Option<[f64; 4]> {
+    let hex = s.trim().strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok().map(|v| v as f64 / 255.0);
+    Some([channel(0)?, channel(2)?, channel(4)?, if hex.len() == 8 { channel(6)? } else { 1.0 }])
+}
+
+/// Value of a matplotlib segment channel at `x`, approaching from the
+/// right (`y1` at a point) or the left (`y0`).
+fn segment_value(points: &[[f64; 3]], x: f64, right: bool) -> f64 {
+    let after = points.partition_point(|p| p[0] < x);
+    let value = match points.get(after) {
+        Some(p) if p[0] == x => {
+            // Of points sharing an x, the first gives y0 and the last y1
+            let last = points[after..].iter().take_while(|q| q[0] == x).last().unwrap_or(p);
+            if right { last[2] } else { p[1] }
+        }
+        Some(p) if after > 0 => {
+            let q = points[after - 1];
+            q[2] + (p[1] - q[2]) * (x - q[0]) / (p[0] - q[0])
+        }
+        Some(p) => p[1],
+        None => points[points.len() - 1][2],
+    };
+    value.clamp(0.0, 1.0)
+}
+
+/// Folder searched for colormap files by name: `colormaps` in the
+/// configuration folder.
+pub fn user_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("com", "imsel", "radar_plotter").map(|dirs| dirs.config_dir().join("colormaps"))
+}
+
+/// `.csv` and `.json` files in `dir`, sorted by name.
+fn colormap_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.is_file()
+                && p.extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("csv") || e.eq_ignore_ascii_case("json"))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Colormaps in [`user_dir`], and the errors of files that failed to load.
+pub fn user_colormaps() -> (Vec<CMap>, Vec<Error>) {
+    let mut cmaps = Vec::new();
+    let mut errors = Vec::new();
+    for path in user_dir().map(|dir| colormap_files(&dir)).unwrap_or_default() {
+        match CustomCMap::load(&path) {
+            Ok(custom) => cmaps.push(CMap::Custom(Arc::new(custom))),
+            Err(e) => errors.push(e),
+        }
+    }
+    (cmaps, errors)
+}
+
+/// A colormap sampled at [`TABLE_SIZE`] evenly spaced points.
+#[derive(Debug, Clone)]
+pub struct ColorTable {
+    colors: Vec<[u8; 4]>,
+}
+
+impl ColorTable {
+    pub fn new(cmap: &CMap) -> Self {
+        let colors = (0..TABLE_SIZE)
+            .map(|i| cmap.eval(i as f64 / (TABLE_SIZE - 1) as f64))
+            .collect();
+        ColorTable { colors }
+    }
+
+    /// Scale the alpha of every entry by `factor` of its position in `[0, 1]`.
+    pub fn fade(&mut self, factor: impl Fn(f64) -> f64) {
+        for (i, color) in self.colors.iter_mut().enumerate() {
+            let f = factor(i as f64 / (TABLE_SIZE - 1) as f64).clamp(0.0, 1.0);
+            color[3] = (color[3] as f64 * f).round() as u8;
+        }
+    }
+
+    /// Color for a normalized value in `[0, 1]`. Values outside are clamped.
+    #[inline]
+    pub fn get(&self, v: f32) -> [u8; 4] {
+        let i = (v.clamp(0.0, 1.0) * (TABLE_SIZE - 1) as f32).round() as usize;
+        self.colors[i]
+    }
+}
+
+fn to_rgba(gradient: Gradient, v: f64) -> [u8; 4] {
+    let Color { r, g, b } = gradient.eval_continuous(v);
+    [r, g, b, 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn csv(text: &str) -> CustomCMap {
+        CustomCMap::parse_csv("test".to_string(), text).unwrap()
+    }
+
+    fn json(text: &str) -> CustomCMap {
+        CustomCMap::parse_json("test".to_string(), text).unwrap()
+    }
+
+    #[test]
+    fn csv_channels_in_either_unit() {
+        for text in ["0,0,0\n255,255,255\n", "0,0,0\n1,1,1\n", "r;g;b\n0;0;0\n255;255;255\n"] {
+            let cmap = csv(text);
+            assert_eq!(cmap.eval(0.5), [128, 128, 128, 255], "{text}");
+            assert!(!cmap.discrete);
+        }
+    }
+
+    #[test]
+    fn csv_hex_colors_and_alpha() {
+        let cmap = csv("# fade in\n#ff000000\n#FF0000\n0,0,255,128\n");
+        assert_eq!(cmap.eval(0.0), [255, 0, 0, 0]);
+        assert_eq!(cmap.eval(0.5), [255, 0, 0, 255]);
+        assert_eq!(cmap.eval(1.0), [0, 0, 255, 128]);
+    }
+
+    #[test]
+    fn csv_positions_are_scaled() {
+        let cmap = csv("value,red,green,blue\n10,0,0,0\n40,255,255,255\n50,255,0,0\n");
+        assert_eq!(cmap.eval(0.375), [128, 128, 128, 255]);
+        assert_eq!(cmap.eval(0.75), [255, 255, 255, 255]);
+        assert_eq!(cmap.eval(0.875), [255, 128, 128, 255]);
+    }
+
+    #[test]
+    fn csv_errors_name_the_line() {
+        let error = CustomCMap::parse_csv("test".to_string(), "0,0,0\n\n0,0\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 3: expected a color");
+        let error = CustomCMap::parse_csv("test".to_string(), "level,color\n5,#000000\n1,#ffffff\n").unwrap_err();
+        assert_eq!(error.to_string(), "positions must increase");
+        assert!(CustomCMap::parse_csv("test".to_string(), "# nothing\n").is_err());
+    }
+
+    #[test]
+    fn json_color_lists() {
+        let cmap = json("[[0.0, 0.0, 0.0], \"#ffffff\"]");
+        assert_eq!(cmap.eval(0.5), [128, 128, 128, 255]);
+        let cmap = json(r##"{"colors": ["#000000", "#ffffff"], "positions": [0, 0.25], "interpolation": "discrete"}"##);
+        assert!(cmap.discrete);
+        // The last color holds for as long as the first
+        assert_eq!(cmap.eval(0.49), [0, 0, 0, 255]);
+        assert_eq!(cmap.eval(0.5), [255, 255, 255, 255]);
+        let mismatched = r##"{"colors": ["#000000"], "positions": [0, 1]}"##;
+        assert!(CustomCMap::parse_json("test".to_string(), mismatched).is_err());
+    }
+
+    #[test]
+    fn json_segment_data() {
+        // Red jumps from 0 to 1 halfway; green ramps; blue and alpha are constant
+        let cmap = json(
+            r#"{
+                "red": [[0, 0, 0], [0.5, 0, 1], [1, 1, 1]],
+                "green": [[0, 0, 0], [1, 1, 1]],
+                "blue": [[0, 0.2, 0.2], [1, 0.2, 0.2]]
+            }"#,
+        );
+        assert_eq!(cmap.eval(0.25), [0, 64, 51, 255]);
+        assert_eq!(cmap.eval(0.5), [255, 128, 51, 255]);
+        assert_eq!(cmap.eval(1.0), [255, 255, 51, 255]);
+        let short = r#"{"red": [[0, 0]], "green": [], "blue": []}"#;
+        let error = CustomCMap::parse_json("test".to_string(), short).unwrap_err();
+        assert_eq!(error.to_string(), "red: expected [x, y0, y1], got [0,0]");
+    }
+
+    #[test]
+    fn built_in_names() {
+        assert_eq!(CMap::find("Turbo").unwrap(), CMap::Turbo);
+        assert_eq!(CMap::find("grey").unwrap().name(), "gray");
+        assert!(CMap::find("no-such-colormap").is_err());
+    }
+
+    #[test]
+    fn discrete_levels_give_last_color_a_band() {
+        let csv = "# discrete\nlevel,color\n5,#000000\n10,#808080\n15,#ffffff\n";
+        let cmap = CustomCMap::parse_csv("levels".to_string(), csv).unwrap();
+        // Bands [5, 10), [10, 15) and [15, 20) scaled onto [0, 1]
+        assert_eq!(cmap.eval(0.0), [0, 0, 0, 255]);
+        assert_eq!(cmap.eval(0.3), [0, 0, 0, 255]);
+        assert_eq!(cmap.eval(0.34), [128, 128, 128, 255]);
+        assert_eq!(cmap.eval(0.67), [255, 255, 255, 255]);
+        assert_eq!(cmap.eval(1.0), [255, 255, 255, 255]);
+        let table = ColorTable::new(&CMap::Custom(Arc::new(cmap)));
+        assert_eq!(table.get(0.9), [255, 255, 255, 255]);
+    }
+}
@@ -1,15 +1,115 @@
 //! Settings persistence
 
+use radar_plotter::animation::{AnimationSettings, FrameDelay};
+use radar_plotter::geo::GeoReference;
+use radar_plotter::heading::{HeadingSettings, HeadingSource};
+use radar_plotter::legend::LegendOptions;
+use radar_plotter::overlay::{parse_hex_color, OverlayOptions};
+use radar_plotter::processing::ProcessingSettings;
+use radar_plotter::sweep::{self, CsvFormat};
+use radar_plotter::validation::ValidationMode;
+use radar_plotter::manifest::ResumeMode;
+use radar_plotter::watch::WatchSettings;
+use radar_plotter::{AlphaRamp, CMap, Normalization, Transfer};
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub pulses: i32,
     pub gap_deg: f64,
+    /// "none", "nearest", "linear" or "cubic"
+    pub gap_fill: String,
+    /// Mounting correction added to every pulse azimuth
+    pub azimuth_offset_deg: f64,
+    /// "last", "mean", "max" or "nearest"
+    pub aggregation: String,
+    /// "none", "constant", "column" or "file"
+    pub heading_source: String,
+    pub vessel_heading_deg: f64,
+    /// CSV column of the vessel heading, by header name or zero-based index
+    pub heading_column: String,
+    pub heading_file: String,
+    /// "north-up" or "head-up"
+    pub orientation: String,
+    /// Single character, "tab" or "space"
+    pub csv_delimiter: String,
+    pub csv_header: bool,
+    /// CSV columns, by header name or zero-based index
+    pub csv_range_column: String,
+    pub csv_gain_column: String,
+    pub csv_angle_column: String,
+    pub csv_echo_column: String,
+    /// Optional columns, empty when unused
+    pub csv_status_column: String,
+    pub csv_scale_column: String,
+    pub ticks_per_rev: i32,
+    /// "off", "warn" or "strict"
+    pub validation: String,
+    pub split_rotations: bool,
     pub image_size: i32,
+    /// "nearest" or "bilinear"
+    pub interpolation: String,
+    /// Subsamples per pixel side at edges, 1 for off
+    pub antialias: i32,
+    /// Built-in colormap or the file stem of a user colormap file
     pub colormap: String,
     pub jobs: i32,
+    /// One of "image", "fixed", "folder", "percentile"
+    pub normalization: String,
+    pub norm_min: f64,
+    pub norm_max: f64,
+    pub norm_percentile: f64,
+    /// One of "linear", "log", "gamma"
+    pub transfer: String,
+    pub transfer_db_range: f64,
+    pub transfer_gamma: f64,
+    /// Echo value below which pixels are transparent, 0 for off
+    pub noise_floor: f64,
+    /// Echo value above which the top color is used, 0 for off
+    pub ceiling: f64,
+    /// Fade weak echoes towards transparent
+    pub alpha_ramp: bool,
+    /// Colormap positions (0-1) where the ramp starts and becomes opaque
+    pub alpha_start: f64,
+    pub alpha_end: f64,
+    pub alpha_gamma: f64,
+    pub animation: bool,
+    /// "gif" or "apng"
+    pub animation_format: String,
+    /// Frame delay in ms (0 = from filename timestamps)
+    pub frame_delay_ms: i32,
+    pub overlay: bool,
+    /// "burn" or "separate"
+    pub overlay_mode: String,
+    pub overlay_rings: i32,
+    pub overlay_spoke_deg: f64,
+    pub overlay_north_marker: bool,
+    pub overlay_labels: bool,
+    /// Hex color, e.g. "#ffffff"
+    pub overlay_color: String,
+    pub overlay_label_color: String,
+    pub overlay_opacity: f64,
+    pub legend: bool,
+    /// "extend" or "corner"
+    pub legend_placement: String,
+    pub legend_colorbar: bool,
+    pub legend_caption: bool,
+    pub legend_color: String,
+    pub georef: bool,
+    pub radar_lat: f64,
+    pub radar_lon: f64,
+    pub radar_heading_deg: f64,
+    pub meters_per_unit: f64,
+    pub kmz: bool,
+    /// "off", "newer" or "manifest"
+    pub resume: String,
+    pub watch: bool,
+    /// Seconds a new file must stay unchanged before it is rendered
+    pub watch_settle_secs: f64,
 }
 
 impl Default for Settings {
@@ -17,13 +117,238 @@ impl Default for Settings {
         Settings {
             pulses: 720,
             gap_deg: 1.0,
+            gap_fill: "linear".to_string(),
+            azimuth_offset_deg: 0.0,
+            aggregation: "last".to_string(),
+            heading_source: "none".to_string(),
+            vessel_heading_deg: 0.0,
+            heading_column: "Heading".to_string(),
+            heading_file: String::new(),
+            orientation: "north-up".to_string(),
+            csv_delimiter: ",".to_string(),
+            csv_header: true,
+            csv_range_column: "Range".to_string(),
+            csv_gain_column: "Gain".to_string(),
+            csv_angle_column: "Angle".to_string(),
+            csv_echo_column: "EchoValues".to_string(),
+            csv_status_column: String::new(),
+            csv_scale_column: String::new(),
+            ticks_per_rev: sweep::DEFAULT_TICKS_PER_REV as i32,
+            validation: "off".to_string(),
+            split_rotations: false,
             image_size: 1735,
+            interpolation: "nearest".to_string(),
+            antialias: 1,
             colormap: "viridis".to_string(),
             jobs: 0,
+            normalization: "image".to_string(),
+            norm_min: 0.0,
+            norm_max: 255.0,
+            norm_percentile: 99.0,
+            transfer: "linear".to_string(),
+            transfer_db_range: 30.0,
+            transfer_gamma: 0.5,
+            noise_floor: 0.0,
+            ceiling: 0.0,
+            alpha_ramp: false,
+            alpha_start: 0.0,
+            alpha_end: 1.0,
+            alpha_gamma: 1.0,
+            animation: false,
+            animation_format: "gif".to_string(),
+            frame_delay_ms: 0,
+            overlay: false,
+            overlay_mode: "burn".to_string(),
+            overlay_rings: 4,
+            overlay_spoke_deg: 30.0,
+            overlay_north_marker: true,
+            overlay_labels: true,
+            overlay_color: "#ffffff".to_string(),
+            overlay_label_color: "#ffffff".to_string(),
+            overlay_opacity: 0.6,
+            legend: false,
+            legend_placement: "extend".to_string(),
+            legend_colorbar: true,
+            legend_caption: true,
+            legend_color: "#ffffff".to_string(),
+            georef: false,
+            radar_lat: 0.0,
+            radar_lon: 0.0,
+            radar_heading_deg: 0.0,
+            meters_per_unit: 1852.0,
+            kmz: false,
+            resume: "off".to_string(),
+            watch: false,
+            watch_settle_secs: 2.0,
         }
     }
 }
 
+impl Settings {
+    /// CSV layout, or an error naming the first invalid entry
+    pub fn csv_format(&self) -> anyhow::Result<CsvFormat> {
+        let column = |what: &str, s: &str| s.parse().with_context(|| format!("CSV {what} column"));
+        Ok(CsvFormat {
+            delimiter: sweep::parse_delimiter(&self.csv_delimiter).context("CSV delimiter")?,
+            header: self.csv_header,
+            range: column("range", &self.csv_range_column)?,
+            gain: column("gain", &self.csv_gain_column)?,
+            angle: column("angle", &self.csv_angle_column)?,
+            echoes: column("echo", &self.csv_echo_column)?,
+            heading: (self.heading_source == "column")
+                .then(|| self.heading_column.parse().ok())
+                .flatten(),
+            status: self.csv_status_column.parse().ok(),
+            scale: self.csv_scale_column.parse().ok(),
+            ticks_per_rev: self.ticks_per_rev.max(2) as u32,
+        })
+    }
+
+    /// Heading source; `None` when off or incomplete
+    pub fn heading(&self) -> Option<HeadingSettings> {
+        let source = match self.heading_source.as_str() {
+            "constant" => HeadingSource::Constant(self.vessel_heading_deg),
+            "column" if !self.heading_column.trim().is_empty() => HeadingSource::Column,
+            "file" if !self.heading_file.trim().is_empty() => {
+                HeadingSource::File(PathBuf::from(self.heading_file.trim()))
+            }
+            _ => return None,
+        };
+        Some(HeadingSettings {
+            source,
+            orientation: self.orientation.parse().unwrap_or_default(),
+        })
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        match self.normalization.as_str() {
+            "fixed" if self.norm_max > self.norm_min => Normalization::Fixed {
+                min: self.norm_min as f32,
+                max: self.norm_max as f32,
+            },
+            "folder" => Normalization::FolderMax,
+            "percentile" => Normalization::Percentile(self.norm_percentile.clamp(0.0, 100.0) as f32),
+            _ => Normalization::ImageMax,
+        }
+    }
+
+    pub fn transfer(&self) -> Transfer {
+        match self.transfer.as_str() {
+            "log" if self.transfer_db_range > 0.0 => Transfer::Decibel {
+                range_db: self.transfer_db_range as f32,
+            },
+            "gamma" if self.transfer_gamma > 0.0 => Transfer::Gamma(self.transfer_gamma as f32),
+            _ => Transfer::Linear,
+        }
+    }
+
+    pub fn alpha(&self) -> AlphaRamp {
+        let (start, end) = (self.alpha_start.clamp(0.0, 1.0), self.alpha_end.clamp(0.0, 1.0));
+        if !self.alpha_ramp || end <= start || self.alpha_gamma <= 0.0 {
+            return AlphaRamp::Opaque;
+        }
+        AlphaRamp::Ramp {
+            start: start as f32,
+            end: end as f32,
+            gamma: self.alpha_gamma as f32,
+        }
+    }
+
+    pub fn animation(&self) -> Option<AnimationSettings> {
+        if !self.animation {
+            return None;
+        }
+        Some(AnimationSettings {
+            format: self.animation_format.parse().ok()?,
+            delay: if self.frame_delay_ms > 0 {
+                FrameDelay::Fixed(self.frame_delay_ms as u32)
+            } else {
+                FrameDelay::Timestamps
+            },
+        })
+    }
+
+    pub fn overlay(&self) -> Option<OverlayOptions> {
+        if !self.overlay {
+            return None;
+        }
+        let defaults = OverlayOptions::default();
+        Some(OverlayOptions {
+            rings: self.overlay_rings.max(0) as u32,
+            spoke_deg: self.overlay_spoke_deg.max(0.0),
+            north_marker: self.overlay_north_marker,
+            labels: self.overlay_labels,
+            line_color: parse_hex_color(&self.overlay_color).unwrap_or(defaults.line_color),
+            label_color: parse_hex_color(&self.overlay_label_color).unwrap_or(defaults.label_color),
+            opacity: self.overlay_opacity as f32,
+            mode: self.overlay_mode.parse().unwrap_or(defaults.mode),
+            ..defaults
+        })
+    }
+
+    pub fn legend(&self) -> Option<LegendOptions> {
+        if !self.legend {
+            return None;
+        }
+        let defaults = LegendOptions::default();
+        Some(LegendOptions {
+            placement: self.legend_placement.parse().unwrap_or(defaults.placement),
+            colorbar: self.legend_colorbar,
+            caption: self.legend_caption,
+            text_color: parse_hex_color(&self.legend_color).unwrap_or(defaults.text_color),
+        })
+    }
+
+    pub fn georef(&self) -> Option<GeoReference> {
+        if !self.georef || self.meters_per_unit <= 0.0 {
+            return None;
+        }
+        Some(GeoReference {
+            lat: self.radar_lat.clamp(-90.0, 90.0),
+            lon: self.radar_lon,
+            heading_deg: self.radar_heading_deg,
+            meters_per_unit: self.meters_per_unit,
+        })
+    }
+
+    /// Settings for a processing run; fails on CSV column entries that the
+    /// CLI would reject
+    pub fn processing_settings(&self) -> anyhow::Result<ProcessingSettings> {
+        Ok(ProcessingSettings {
+            pulses: self.pulses.max(1) as usize,
+            gap_deg: self.gap_deg,
+            gap_fill: self.gap_fill.parse().unwrap_or_default(),
+            azimuth_offset_deg: self.azimuth_offset_deg,
+            aggregation: self.aggregation.parse().unwrap_or_default(),
+            heading: self.heading(),
+            csv_format: self.csv_format()?,
+            split_rotations: self.split_rotations,
+            validation: self.validation.parse().unwrap_or(ValidationMode::Off),
+            size: self.image_size.max(1) as u32,
+            interpolation: self.interpolation.parse().unwrap_or_default(),
+            antialias: self.antialias.clamp(1, 8) as u32,
+            colormap: CMap::find(&self.colormap).unwrap_or(CMap::Viridis),
+            normalization: self.normalization(),
+            transfer: self.transfer(),
+            noise_floor: (self.noise_floor > 0.0).then_some(self.noise_floor as f32),
+            ceiling: (self.ceiling > 0.0).then_some(self.ceiling as f32),
+            alpha: self.alpha(),
+            animation: self.animation(),
+            overlay: self.overlay(),
+            legend: self.legend(),
+            georef: self.georef(),
+            kmz: self.kmz,
+            resume: self.resume.parse().unwrap_or(ResumeMode::Off),
+            watch: self.watch.then(|| WatchSettings {
+                settle: Duration::from_secs_f64(self.watch_settle_secs.max(0.0)),
+                ..WatchSettings::default()
+            }),
+            preview: true,
+            jobs: self.jobs.max(0) as usize,
+        })
+    }
+}
+
 
 fn settings_path() -> Option<PathBuf> {
     directories::ProjectDirs::from("com", "imsel", "radar_plotter")
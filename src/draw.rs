@@ -0,0 +1,222 @@
+//! Anti-aliased drawing primitives for annotating RGBA images
+
+use image::{Rgba, RgbaImage};
+
+use crate::font;
+
+/// Composite `color` over the pixel at (x, y) with the given coverage.
+///
+/// Works on transparent backgrounds, so overlays can be drawn on an empty
+/// canvas and layered later.
+pub fn blend(img: &mut RgbaImage, x: i64, y: i64, color: Rgba<u8>, coverage: f32) {
+    if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+        return;
+    }
+    let src_a = color[3] as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+    if src_a <= 0.0 {
+        return;
+    }
+    let dst = img.get_pixel_mut(x as u32, y as u32);
+    let dst_a = dst[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    for i in 0..3 {
+        let c = (color[i] as f32 * src_a + dst[i] as f32 * dst_a * (1.0 - src_a)) / out_a;
+        dst[i] = c.round().clamp(0.0, 255.0) as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+/// Circle outline of radius `r` centred on (cx, cy).
+pub fn circle(img: &mut RgbaImage, cx: f64, cy: f64, r: f64, width: f64, color: Rgba<u8>) {
+    let half = width / 2.0 + 0.5;
+    let outer = r + half;
+    let inner = (r - half).max(0.0);
+    let y0 = (cy - outer).floor() as i64;
+    let y1 = (cy + outer).ceil() as i64;
+
+    for y in y0..=y1 {
+        let dy = y as f64 + 0.5 - cy;
+        if dy.abs() > outer {
+            continue;
+        }
+        let x_out = (outer * outer - dy * dy).sqrt();
+        let x_in = if dy.abs() < inner { (inner * inner - dy * dy).sqrt() } else { 0.0 };
+        // Left and right spans of the ring on this row
+        for (a, b) in [(cx - x_out, cx - x_in), (cx + x_in, cx + x_out)] {
+            for x in a.floor() as i64..=b.ceil() as i64 {
+                let dx = x as f64 + 0.5 - cx;
+                let d = (dx * dx + dy * dy).sqrt();
+                let coverage = half - (d - r).abs();
+                if coverage > 0.0 {
+                    blend(img, x, y, color, coverage as f32);
+                }
+            }
+        }
+    }
+}
+
+/// Line segment from (x0, y0) to (x1, y1).
+pub fn line(img: &mut RgbaImage, (x0, y0): (f64, f64), (x1, y1): (f64, f64), width: f64, color: Rgba<u8>) {
+    let half = width / 2.0 + 0.5;
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let len2 = dx * dx + dy * dy;
+    if len2 == 0.0 {
+        return;
+    }
+    let len = len2.sqrt();
+    // Step along the major axis and cover a band across it
+    let steep = dy.abs() > dx.abs();
+    let (a0, a1) = if steep { (y0.min(y1), y0.max(y1)) } else { (x0.min(x1), x0.max(x1)) };
+    let band = (half * len / if steep { dy.abs() } else { dx.abs() }).ceil() as i64 + 1;
+
+    for a in (a0 - half).floor() as i64..=(a1 + half).ceil() as i64 {
+        let ac = a as f64 + 0.5;
+        let t = if steep { (ac - y0) / dy } else { (ac - x0) / dx };
+        let b_center = if steep { x0 + t * dx } else { y0 + t * dy };
+        for b in b_center.floor() as i64 - band..=b_center.floor() as i64 + band {
+            let (px, py) = if steep { (b, a) } else { (a, b) };
+            let (qx, qy) = (px as f64 + 0.5 - x0, py as f64 + 0.5 - y0);
+            let s = ((qx * dx + qy * dy) / len2).clamp(0.0, 1.0);
+            let (ex, ey) = (qx - s * dx, qy - s * dy);
+            let coverage = half - (ex * ex + ey * ey).sqrt();
+            if coverage > 0.0 {
+                blend(img, px, py, color, coverage as f32);
+            }
+        }
+    }
+}
+
+/// Filled triangle with anti-aliased edges.
+pub fn triangle(img: &mut RgbaImage, pts: [(f64, f64); 3], color: Rgba<u8>) {
+    let x0 = pts.iter().map(|p| p.0).fold(f64::INFINITY, f64::min).floor() as i64;
+    let x1 = pts.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max).ceil() as i64;
+    let y0 = pts.iter().map(|p| p.1).fold(f64::INFINITY, f64::min).floor() as i64;
+    let y1 = pts.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max).ceil() as i64;
+    let area = (pts[1].0 - pts[0].0) * (pts[2].1 - pts[0].1) - (pts[2].0 - pts[0].0) * (pts[1].1 - pts[0].1);
+    if area == 0.0 {
+        return;
+    }
+
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            // Signed distance to the nearest edge, positive inside
+            let mut d = f64::INFINITY;
+            for i in 0..3 {
+                let (ax, ay) = pts[i];
+                let (bx, by) = pts[(i + 1) % 3];
+                let (ex, ey) = (bx - ax, by - ay);
+                let cross = (ex * (py - ay) - ey * (px - ax)) * area.signum();
+                d = d.min(cross / (ex * ex + ey * ey).sqrt());
+            }
+            let coverage = d + 0.5;
+            if coverage > 0.0 {
+                blend(img, x, y, color, coverage as f32);
+            }
+        }
+    }
+}
+
+/// Text with its top-left corner at (x, y), each font pixel `scale` image pixels wide.
+pub fn text(img: &mut RgbaImage, x: i64, y: i64, text: &str, scale: u32, color: Rgba<u8>) {
+    let scale = scale.max(1) as i64;
+    for (i, c) in text.chars().enumerate() {
+        let gx = x + i as i64 * font::ADVANCE as i64 * scale;
+        for (col, bits) in font::glyph(c).iter().enumerate() {
+            for row in 0..font::GLYPH_HEIGHT as i64 {
+                if bits & (1 << row) == 0 {
+                    continue;
+                }
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        blend(img, gx + col as i64 * scale + sx, y + row * scale + sy, color, 1.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Text with a one font-pixel outline in `halo`, readable on any background.
+pub fn text_with_halo(img: &mut RgbaImage, x: i64, y: i64, s: &str, scale: u32, color: Rgba<u8>, halo: Rgba<u8>) {
+    let step = scale.max(1) as i64;
+    for (ox, oy) in [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, 1), (-1, 1), (1, -1)] {
+        text(img, x + ox * step, y + oy * step, s, scale, halo);
+    }
+    text(img, x, y, s, scale, color);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    #[test]
+    fn blend_composites_over_the_pixel() {
+        let mut img = RgbaImage::new(2, 1);
+        blend(&mut img, 0, 0, Rgba([200, 100, 0, 255]), 0.5);
+        assert_eq!(img.get_pixel(0, 0).0, [200, 100, 0, 128]);
+        img.put_pixel(1, 0, WHITE);
+        blend(&mut img, 1, 0, Rgba([0, 0, 0, 255]), 0.5);
+        assert_eq!(img.get_pixel(1, 0).0, [128, 128, 128, 255]);
+        // Outside the image and zero coverage leave it alone
+        blend(&mut img, -1, 0, WHITE, 1.0);
+        blend(&mut img, 2, 0, WHITE, 1.0);
+        blend(&mut img, 0, 0, WHITE, 0.0);
+        assert_eq!(img.get_pixel(0, 0).0, [200, 100, 0, 128]);
+    }
+
+    #[test]
+    fn line_covers_the_segment_only() {
+        let mut img = RgbaImage::new(20, 20);
+        line(&mut img, (5.0, 10.0), (15.0, 10.0), 1.0, WHITE);
+        // Pixel rows 9 and 10 straddle y = 10
+        for x in 5..15 {
+            assert_eq!(img.get_pixel(x, 9)[3], 128, "({x}, 9)");
+            assert_eq!(img.get_pixel(x, 10)[3], 128, "({x}, 10)");
+        }
+        assert_eq!(img.get_pixel(10, 8)[3], 0);
+        assert_eq!(img.get_pixel(10, 11)[3], 0);
+        assert_eq!(img.get_pixel(2, 10)[3], 0);
+        assert_eq!(img.get_pixel(17, 10)[3], 0);
+    }
+
+    #[test]
+    fn circle_is_drawn_at_its_radius() {
+        let mut img = RgbaImage::new(40, 40);
+        circle(&mut img, 20.0, 20.0, 10.5, 1.0, WHITE);
+        // Pixels on the ring are all but fully covered
+        for (x, y) in [(30, 20), (9, 20), (20, 30), (20, 9)] {
+            assert!(img.get_pixel(x, y)[3] > 250, "({x}, {y})");
+        }
+        assert_eq!(img.get_pixel(20, 20)[3], 0);
+        assert_eq!(img.get_pixel(25, 20)[3], 0);
+        assert_eq!(img.get_pixel(33, 20)[3], 0);
+    }
+
+    #[test]
+    fn triangle_is_filled() {
+        let mut img = RgbaImage::new(20, 20);
+        triangle(&mut img, [(2.0, 2.0), (18.0, 2.0), (2.0, 18.0)], WHITE);
+        assert_eq!(img.get_pixel(5, 5)[3], 255);
+        assert_eq!(img.get_pixel(15, 15)[3], 0);
+        // A pixel centred on the long edge is half covered
+        assert_eq!(img.get_pixel(9, 10)[3], 128);
+        assert_eq!(img.get_pixel(10, 0)[3], 0);
+    }
+
+    #[test]
+    fn text_draws_scaled_glyph_pixels() {
+        let mut img = RgbaImage::new(12, 16);
+        text(&mut img, 1, 1, "|", 2, WHITE);
+        // The bar is the third glyph column, seven font pixels tall
+        for y in 1..15 {
+            assert_eq!(img.get_pixel(5, y)[3], 255);
+            assert_eq!(img.get_pixel(6, y)[3], 255);
+        }
+        assert_eq!(img.get_pixel(4, 5)[3], 0);
+        assert_eq!(img.get_pixel(7, 5)[3], 0);
+        assert_eq!(img.get_pixel(5, 15)[3], 0);
+    }
+}
@@ -0,0 +1,226 @@
+//! Georeferencing of rendered PPI images
+//!
+//! Images are referenced to an azimuthal equidistant projection centred on
+//! the radar, which keeps ranges and bearings from the radar exact. Each PNG
+//! gets an ESRI world file (`.pgw`) and a projection file (`.prj`) next to it,
+//! which GIS tools such as QGIS and GDAL pick up automatically.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Radar position and scale needed to place images on a map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoReference {
+    /// Radar latitude in degrees (WGS 84)
+    pub lat: f64,
+    /// Radar longitude in degrees (WGS 84)
+    pub lon: f64,
+    /// Direction the top of the image points, degrees clockwise from north
+    pub heading_deg: f64,
+    /// Meters per range unit, so the disk radius is `range * meters_per_unit`
+    pub meters_per_unit: f64,
+}
+
+/// Six-parameter affine transform from pixel to map coordinates, in world
+/// file order: x scale, y rotation, x rotation, y scale, x and y of the
+/// upper-left pixel centre.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldFile {
+    pub a: f64,
+    pub d: f64,
+    pub b: f64,
+    pub e: f64,
+    pub c: f64,
+    pub f: f64,
+}
+
+impl WorldFile {
+    /// Contents of a `.pgw` file.
+    pub fn to_pgw(&self) -> String {
+        // Adding zero turns -0.0 into 0.0
+        let [a, d, b, e, c, f] = [self.a, self.d, self.b, self.e, self.c, self.f].map(|v| v + 0.0);
+        format!("{a:.10}\n{d:.10}\n{b:.10}\n{e:.10}\n{c:.6}\n{f:.6}\n")
+    }
+}
+
+/// Mean Earth radius in meters, used for the KML bounding box
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// KML `LatLonBox`: edges in degrees and counter-clockwise rotation about
+/// the box centre.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatLonBox {
+    pub north: f64,
+    pub south: f64,
+    pub east: f64,
+    pub west: f64,
+    pub rotation: f64,
+}
+
+impl GeoReference {
+    /// The reference of an image whose top points `deg` further clockwise,
+    /// such as a head-up image.
+    pub fn turned(&self, deg: f64) -> Self {
+        GeoReference {
+            heading_deg: self.heading_deg + deg,
+            ..*self
+        }
+    }
+
+    /// Disk radius in meters for a sweep displayed out to `range_max` range units.
+    pub fn radius_m(&self, range_max: f64) -> f64 {
+        range_max * self.meters_per_unit
+    }
+
+    /// Transform for a `size` x `size` PPI image whose disk edge is at `range_max`.
+    ///
+    /// Map coordinates are meters east and north of the radar. Extra rows
+    /// below the disk, such as a legend strip, do not change the transform.
+    pub fn world_file(&self, size: u32, range_max: f64) -> WorldFile {
+        let half = size as f64 / 2.0;
+        let px = self.radius_m(range_max) / half;
+        let (sin_h, cos_h) = self.heading_deg.to_radians().sin_cos();
+
+        // Upper-left pixel centre in image coordinates: right and up from the radar
+        let u0 = (0.5 - half) * px;
+        let v0 = (half - 0.5) * px;
+        WorldFile {
+            a: px * cos_h,
+            d: -px * sin_h,
+            b: -px * sin_h,
+            e: -px * cos_h,
+            c: u0 * cos_h + v0 * sin_h,
+            f: -u0 * sin_h + v0 * cos_h,
+        }
+    }
+
+    /// Bounding box for a `width` x `height` image whose disk fills the top
+    /// `width` x `width` square, with the disk edge at `range_max`.
+    ///
+    /// Uses a local flat-Earth approximation around the radar, which is
+    /// accurate to well under a pixel at radar ranges.
+    pub fn lat_lon_box(&self, width: u32, height: u32, range_max: f64) -> LatLonBox {
+        let px = self.radius_m(range_max) / (width as f64 / 2.0);
+        let (half_w, half_h) = (width as f64 * px / 2.0, height as f64 * px / 2.0);
+        // Image centre relative to the radar, rotated onto east/north
+        let v = -(height as f64 - width as f64) / 2.0 * px;
+        let (sin_h, cos_h) = self.heading_deg.to_radians().sin_cos();
+        let (east, north) = (v * sin_h, v * cos_h);
+
+        let m_per_deg_lat = EARTH_RADIUS_M.to_radians();
+        let m_per_deg_lon = m_per_deg_lat * self.lat.to_radians().cos().max(1e-6);
+        let lat = self.lat + north / m_per_deg_lat;
+        let lon = self.lon + east / m_per_deg_lon;
+        LatLonBox {
+            north: lat + half_h / m_per_deg_lat,
+            south: lat - half_h / m_per_deg_lat,
+            east: lon + half_w / m_per_deg_lon,
+            west: lon - half_w / m_per_deg_lon,
+            rotation: (180.0 - self.heading_deg).rem_euclid(360.0) - 180.0,
+        }
+    }
+
+    /// ESRI WKT for the azimuthal equidistant projection centred on the radar.
+    pub fn prj(&self) -> String {
+        format!(
+            "PROJCS[\"Radar_Azimuthal_Equidistant\",\
+             GEOGCS[\"GCS_WGS_1984\",DATUM[\"D_WGS_1984\",SPHEROID[\"WGS_1984\",6378137.0,298.257223563]],\
+             PRIMEM[\"Greenwich\",0.0],UNIT[\"Degree\",0.0174532925199433]],\
+             PROJECTION[\"Azimuthal_Equidistant\"],\
+             PARAMETER[\"False_Easting\",0.0],PARAMETER[\"False_Northing\",0.0],\
+             PARAMETER[\"Central_Meridian\",{}],PARAMETER[\"Latitude_Of_Origin\",{}],\
+             UNIT[\"Meter\",1.0]]",
+            self.lon, self.lat
+        )
+    }
+
+    /// Write `.pgw` and `.prj` sidecar files next to `image_path`.
+    pub fn write_sidecars(&self, image_path: &Path, size: u32, range_max: f64) -> Result<()> {
+        let pgw = image_path.with_extension("pgw");
+        fs::write(&pgw, self.world_file(size, range_max).to_pgw())
+            .with_context(|| format!("writing {}", pgw.display()))?;
+        let prj = image_path.with_extension("prj");
+        fs::write(&prj, self.prj())
+            .with_context(|| format!("writing {}", prj.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    const RADAR: GeoReference = GeoReference {
+        lat: 34.0,
+        lon: -81.0,
+        heading_deg: 0.0,
+        meters_per_unit: 1000.0,
+    };
+
+    #[test]
+    fn world_file_north_up() {
+        // 100 px across a 1000 m radius: 20 m per pixel
+        let wf = RADAR.world_file(100, 1.0);
+        assert_eq!((wf.a, wf.d, wf.b, wf.e), (20.0, 0.0, 0.0, -20.0));
+        assert_close(wf.c, -990.0);
+        assert_close(wf.f, 990.0);
+        // The centre of the image is the radar
+        assert_close(wf.a * 49.5 + wf.b * 49.5 + wf.c, 0.0);
+        assert_close(wf.d * 49.5 + wf.e * 49.5 + wf.f, 0.0);
+        assert_eq!(
+            wf.to_pgw(),
+            "20.0000000000\n0.0000000000\n0.0000000000\n-20.0000000000\n-990.000000\n990.000000\n"
+        );
+    }
+
+    #[test]
+    fn world_file_turned() {
+        // Top of the image pointing east: the upper-left pixel is north-east
+        let wf = RADAR.turned(90.0).world_file(100, 1.0);
+        assert_close(wf.a, 0.0);
+        assert_close(wf.d, -20.0);
+        assert_close(wf.b, -20.0);
+        assert_close(wf.e, 0.0);
+        assert_close(wf.c, 990.0);
+        assert_close(wf.f, 990.0);
+    }
+
+    #[test]
+    fn lat_lon_box_north_up() {
+        let m_per_deg = EARTH_RADIUS_M.to_radians();
+        let b = RADAR.lat_lon_box(100, 100, 1.0);
+        assert_close(b.north, 34.0 + 1000.0 / m_per_deg);
+        assert_close(b.south, 34.0 - 1000.0 / m_per_deg);
+        assert_close(b.east, -81.0 + 1000.0 / (m_per_deg * 34.0_f64.to_radians().cos()));
+        assert_close(b.west, -81.0 - 1000.0 / (m_per_deg * 34.0_f64.to_radians().cos()));
+        assert_close(b.rotation, 0.0);
+    }
+
+    #[test]
+    fn lat_lon_box_with_legend_strip() {
+        // 20 rows below the disk move the box centre 200 m south
+        let m_per_deg = EARTH_RADIUS_M.to_radians();
+        let b = RADAR.lat_lon_box(100, 120, 1.0);
+        assert_close(b.north, 34.0 + 1000.0 / m_per_deg);
+        assert_close(b.south, 34.0 - 1400.0 / m_per_deg);
+    }
+
+    #[test]
+    fn lat_lon_box_rotation_is_counter_clockwise() {
+        assert_close(RADAR.turned(90.0).lat_lon_box(100, 100, 1.0).rotation, -90.0);
+        assert_close(RADAR.turned(270.0).lat_lon_box(100, 100, 1.0).rotation, 90.0);
+    }
+
+    #[test]
+    fn prj_is_centred_on_radar() {
+        let prj = RADAR.prj();
+        assert!(prj.contains("PARAMETER[\"Central_Meridian\",-81]"));
+        assert!(prj.contains("PARAMETER[\"Latitude_Of_Origin\",34]"));
+    }
+}
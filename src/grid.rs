@@ -0,0 +1,374 @@
+//! Regularization of raw pulses onto a fixed angular grid
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+
+use crate::sweep::Sweep;
+
+/// Echo data resampled onto `pulses` equal angular steps.
+///
+/// Row `p` covers azimuths `[theta_edges[p], theta_edges[p + 1])`. Pulses
+/// without data are filled with `NaN`.
+#[derive(Debug, Clone)]
+pub struct PolarGrid {
+    /// Angular bin edges in radians, `pulses + 1` entries
+    pub theta_edges: Vec<f64>,
+    /// Echo values, `pulses` rows of `n_bins` values
+    pub bins: Vec<Vec<f32>>,
+}
+
+impl PolarGrid {
+    /// Number of angular steps per revolution.
+    pub fn pulses(&self) -> usize {
+        self.bins.len()
+    }
+
+    /// Number of range bins per pulse.
+    pub fn n_bins(&self) -> usize {
+        self.bins.first().map_or(0, |row| row.len())
+    }
+
+    /// Whether the grid holds no pulses.
+    pub fn is_empty(&self) -> bool {
+        self.bins.is_empty()
+    }
+}
+
+/// How pulses falling into the same grid step are combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Keep the last pulse in angle order
+    #[default]
+    Last,
+    /// Average the pulses bin by bin, reducing noise at low pulse counts
+    Mean,
+    /// Keep the strongest echo of each bin, so small targets stay visible
+    Max,
+    /// Keep the pulse closest to the center of the step
+    Nearest,
+}
+
+impl FromStr for Aggregation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "last" => Ok(Self::Last),
+            "mean" => Ok(Self::Mean),
+            "max" => Ok(Self::Max),
+            "nearest" => Ok(Self::Nearest),
+            _ => Err(anyhow!("Unknown aggregation: {s}")),
+        }
+    }
+}
+
+/// How runs of empty grid steps are filled from their neighbours.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GapFill {
+    /// Leave gaps empty
+    None,
+    /// Copy the closer neighbouring pulse
+    Nearest,
+    /// Blend linearly between the neighbouring pulses
+    #[default]
+    Linear,
+    /// Cubic Hermite curve through the two pulses on each side of the gap
+    Cubic,
+}
+
+impl FromStr for GapFill {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "nearest" => Ok(Self::Nearest),
+            "linear" => Ok(Self::Linear),
+            "cubic" => Ok(Self::Cubic),
+            _ => Err(anyhow!("Unknown gap fill: {s}")),
+        }
+    }
+}
+
+impl GapFill {
+    /// Value a fraction `t` of the way across a gap of `steps` steps from
+    /// `a` to `b`, where `before` and `after` are one step outside the gap.
+    fn value(self, t: f32, steps: f32, before: f32, a: f32, b: f32, after: f32) -> f32 {
+        match self {
+            Self::None => f32::NAN,
+            Self::Nearest => {
+                if t < 0.5 { a } else { b }
+            }
+            Self::Linear => a * (1.0 - t) + b * t,
+            Self::Cubic => {
+                // Tangents over the gap from central differences; echoes are never negative
+                let m0 = (b - before) / (steps + 1.0) * steps;
+                let m1 = (after - a) / (steps + 1.0) * steps;
+                let (t2, t3) = (t * t, t * t * t);
+                let v = (2.0 * t3 - 3.0 * t2 + 1.0) * a
+                    + (t3 - 2.0 * t2 + t) * m0
+                    + (3.0 * t2 - 2.0 * t3) * b
+                    + (t3 - t2) * m1;
+                v.max(0.0)
+            }
+        }
+    }
+}
+
+/// How a sweep is resampled onto a [`PolarGrid`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridOptions {
+    /// Angular steps per revolution
+    pub pulses: usize,
+    /// Widest gap in radians that is filled by interpolation
+    pub gap_thresh: f64,
+    /// Filling of gaps up to `gap_thresh`
+    pub gap_fill: GapFill,
+    /// Mounting correction in radians, added to every pulse azimuth so that
+    /// 0 is north (or the bow) when encoder zero is not
+    pub azimuth_offset: f64,
+    /// Combining of pulses that fall into the same step
+    pub aggregation: Aggregation,
+}
+
+impl Default for GridOptions {
+    fn default() -> Self {
+        GridOptions {
+            pulses: 720,
+            gap_thresh: 1.0_f64.to_radians(),
+            gap_fill: GapFill::Linear,
+            azimuth_offset: 0.0,
+            aggregation: Aggregation::Last,
+        }
+    }
+}
+
+/// Regularize a sweep onto a fixed grid of `opts.pulses` steps.
+///
+/// Pulse azimuths are first rotated by `opts.azimuth_offset`. Pulses that
+/// fall into the same step are combined as set by `opts.aggregation`. Runs
+/// of empty steps spanning at most `opts.gap_thresh` radians are filled from
+/// their neighbours as set by `opts.gap_fill`.
+pub fn regularize(sweep: &Sweep, opts: &GridOptions) -> PolarGrid {
+    let GridOptions { pulses, gap_thresh, gap_fill, azimuth_offset, aggregation } = *opts;
+    let angles: Vec<f64> = sweep
+        .angles
+        .iter()
+        .map(|a| (a + azimuth_offset).rem_euclid(2.0 * PI))
+        .collect();
+    let bins = &sweep.bins;
+    if bins.is_empty() || pulses == 0 {
+        return PolarGrid { theta_edges: Vec::new(), bins: Vec::new() };
+    }
+
+    let n_bins = bins[0].len();
+    let mut idx: Vec<usize> = (0..angles.len()).collect();
+    idx.sort_by(|&a, &b| angles[a].total_cmp(&angles[b]));
+
+    let step_rad = 2.0 * PI / pulses as f64;
+    let mut bins_resampled = vec![vec![f32::NAN; n_bins]; pulses];
+    // Echoes summed into each cell for the mean, and how far the kept pulse
+    // of each step is from its center
+    let mut counts = match aggregation {
+        Aggregation::Mean => vec![vec![0u32; n_bins]; pulses],
+        _ => Vec::new(),
+    };
+    let mut kept_offset = vec![f64::INFINITY; pulses];
+
+    for &i in &idx {
+        let theta = angles[i];
+        let pulse = ((theta / (2.0 * PI)) * pulses as f64).floor() as usize % pulses;
+        let row = &mut bins_resampled[pulse];
+        match aggregation {
+            Aggregation::Last => *row = bins[i].clone(),
+            Aggregation::Nearest => {
+                let center = (pulse as f64 + 0.5) * step_rad;
+                let offset = ((theta - center + PI).rem_euclid(2.0 * PI) - PI).abs();
+                if offset < kept_offset[pulse] {
+                    kept_offset[pulse] = offset;
+                    *row = bins[i].clone();
+                }
+            }
+            Aggregation::Mean | Aggregation::Max => {
+                // Missing echoes don't count towards the mean
+                for (j, (dst, &v)) in row.iter_mut().zip(&bins[i]).enumerate() {
+                    if v.is_nan() {
+                        continue;
+                    }
+                    *dst = if dst.is_nan() {
+                        v
+                    } else if aggregation == Aggregation::Mean {
+                        *dst + v
+                    } else {
+                        dst.max(v)
+                    };
+                    if let Some(count) = counts.get_mut(pulse) {
+                        count[j] += 1;
+                    }
+                }
+            }
+        }
+    }
+    for (row, count) in bins_resampled.iter_mut().zip(&counts) {
+        for (v, &n) in row.iter_mut().zip(count) {
+            if n > 1 {
+                *v /= n as f32;
+            }
+        }
+    }
+
+    let mut has_data: Vec<bool> = bins_resampled
+        .iter()
+        .map(|row| row.iter().any(|v| v.is_finite()))
+        .collect();
+
+    let mut i = 0;
+    while i < pulses {
+        if has_data[i] {
+            i += 1;
+            continue;
+        }
+        let run_start = i;
+        while i < pulses && !has_data[i] {
+            i += 1;
+        }
+        let run_end = i - 1;
+
+        let mut prev = (run_start + pulses - 1) % pulses;
+        while !has_data[prev] && prev != run_start {
+            prev = (prev + pulses - 1) % pulses;
+        }
+        let mut next = run_end % pulses;
+        next = (next + 1) % pulses;
+        while !has_data[next] && next != run_start {
+            next = (next + 1) % pulses;
+        }
+
+        if !has_data[prev] || !has_data[next] {
+            continue;
+        }
+
+        let gap_steps = (next + pulses - prev) % pulses;
+        if gap_steps == 0 {
+            continue;
+        }
+        let missing_len = gap_steps - 1;
+        let gap_angle = gap_steps as f64 * step_rad;
+
+        if gap_fill != GapFill::None && gap_angle <= gap_thresh {
+            let prev_row = bins_resampled[prev].clone();
+            let next_row = bins_resampled[next].clone();
+            // One step further out, for the cubic tangents
+            let outer_row = |step: usize| match gap_fill {
+                GapFill::Cubic if has_data[step] => bins_resampled[step].clone(),
+                _ => Vec::new(),
+            };
+            let before_row = outer_row((prev + pulses - 1) % pulses);
+            let after_row = outer_row((next + 1) % pulses);
+            let steps = gap_steps as f32;
+            for k in 1..=missing_len {
+                let t = k as f32 / steps;
+                let idx_fill = (prev + k) % pulses;
+                let filled: Vec<f32> = prev_row
+                    .iter()
+                    .zip(next_row.iter())
+                    .enumerate()
+                    .map(|(j, (&a, &b))| {
+                        let before = before_row.get(j).copied().filter(|v| v.is_finite()).unwrap_or(a);
+                        let after = after_row.get(j).copied().filter(|v| v.is_finite()).unwrap_or(b);
+                        gap_fill.value(t, steps, before, a, b, after)
+                    })
+                    .collect();
+                bins_resampled[idx_fill] = filled;
+                has_data[idx_fill] = true;
+            }
+        }
+    }
+
+    let theta_edges: Vec<f64> = (0..=pulses)
+        .map(|p| p as f64 * 2.0 * PI / pulses as f64)
+        .collect();
+    PolarGrid { theta_edges, bins: bins_resampled }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sweep with one row of `bins` per angle in degrees
+    fn sweep(angles_deg: &[f64], bins: &[&[f32]]) -> Sweep {
+        Sweep {
+            angles: angles_deg.iter().map(|a| a.to_radians()).collect(),
+            bins: bins.iter().map(|row| row.to_vec()).collect(),
+            range_setting: 3,
+            gain: 60,
+            scale: None,
+            timestamp: "20240101120000".to_string(),
+            rotation: None,
+            heading: None,
+        }
+    }
+
+    /// Steps 2 and 3 of an 8-step grid filled by `gap_fill`
+    fn filled(gap_fill: GapFill, gap_deg: f64) -> [f32; 2] {
+        let centers: Vec<f64> = [0, 1, 4, 5, 6, 7].iter().map(|&k| 22.5 + 45.0 * k as f64).collect();
+        let sweep = sweep(&centers, &[&[0.0], &[2.0], &[8.0], &[20.0], &[10.0], &[0.0]]);
+        let opts = GridOptions { pulses: 8, gap_thresh: gap_deg.to_radians(), gap_fill, ..GridOptions::default() };
+        let grid = regularize(&sweep, &opts);
+        [grid.bins[2][0], grid.bins[3][0]]
+    }
+
+    #[test]
+    fn gap_fill_methods() {
+        assert_eq!(filled(GapFill::Nearest, 180.0), [2.0, 8.0]);
+        assert_eq!(filled(GapFill::Linear, 180.0), [4.0, 6.0]);
+        // Hermite tangents from the steps on either side: 6 going in, 13.5 coming out
+        let [a, b] = filled(GapFill::Cubic, 180.0);
+        assert!((a - 31.0 / 9.0).abs() < 1e-5 && (b - 44.0 / 9.0).abs() < 1e-5, "{a} {b}");
+        assert!(filled(GapFill::None, 180.0).iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn gaps_wider_than_threshold_stay_empty() {
+        // The gap spans three steps of 45°
+        for gap_fill in [GapFill::None, GapFill::Nearest, GapFill::Linear, GapFill::Cubic] {
+            assert!(filled(gap_fill, 134.0).iter().all(|v| v.is_nan()), "{gap_fill:?}");
+        }
+        assert_eq!(filled(GapFill::Linear, 135.0), [4.0, 6.0]);
+        assert!(GapFill::None.value(0.25, 4.0, 1.0, 2.0, 3.0, 4.0).is_nan());
+    }
+
+    #[test]
+    fn aggregation_of_pulses_in_one_step() {
+        let sweep = sweep(&[10.0, 40.0, 80.0], &[&[1.0, 5.0], &[2.0, f32::NAN], &[6.0, 2.0]]);
+        let grid = |aggregation| {
+            let opts = GridOptions { pulses: 4, gap_fill: GapFill::None, aggregation, ..GridOptions::default() };
+            regularize(&sweep, &opts).bins[0].clone()
+        };
+        assert_eq!(grid(Aggregation::Last), [6.0, 2.0]);
+        assert_eq!(grid(Aggregation::Mean), [3.0, 3.5]);
+        assert_eq!(grid(Aggregation::Max), [6.0, 5.0]);
+        assert_eq!(grid(Aggregation::Nearest)[0], 2.0);
+        assert_eq!("MEAN".parse::<Aggregation>().unwrap(), Aggregation::Mean);
+        assert!("median".parse::<Aggregation>().is_err());
+    }
+
+    #[test]
+    fn azimuth_offset_turns_pulses() {
+        let opts = GridOptions { pulses: 360, azimuth_offset: 10.0_f64.to_radians(), ..GridOptions::default() };
+        let grid = regularize(&sweep(&[0.5, 355.5], &[&[1.0], &[2.0]]), &opts);
+        assert_eq!(grid.bins[10], [1.0]);
+        // Wraps past north
+        assert_eq!(grid.bins[5], [2.0]);
+        assert!(grid.bins[0][0].is_nan());
+    }
+
+    #[test]
+    fn non_finite_angle_does_not_panic() {
+        let grid = regularize(&sweep(&[0.0, f64::NAN, 90.0], &[&[1.0], &[2.0], &[3.0]]), &GridOptions::default());
+        assert_eq!(grid.pulses(), 720);
+        assert_eq!(grid.bins[180], [3.0]);
+    }
+}
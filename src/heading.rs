@@ -0,0 +1,237 @@
+//! Vessel heading for bow-relative sweeps
+//!
+//! A radar on a moving vessel measures bearings from the bow. With the
+//! heading of each sweep known, images can be rendered north-up (rotated by
+//! the heading) or head-up (bow at the top, as recorded). The heading is a
+//! constant, a column of the capture CSV, or looked up by filename timestamp
+//! in a separate heading file such as an export of NMEA `HDT` sentences.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Error, Result};
+
+use crate::timestamp::Timestamp;
+
+/// Seconds before the first or after the last heading file entry for which
+/// the nearest entry is still used
+const MAX_EXTRAPOLATION_SECS: f64 = 60.0;
+
+/// Which way is up in rendered images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Orientation {
+    /// North at the top; sweeps are rotated by the heading
+    #[default]
+    NorthUp,
+    /// Bow at the top, as the sweeps were recorded
+    HeadUp,
+}
+
+impl FromStr for Orientation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "north-up" | "north" => Ok(Self::NorthUp),
+            "head-up" | "head" => Ok(Self::HeadUp),
+            _ => Err(anyhow!("Unknown orientation: {s}")),
+        }
+    }
+}
+
+impl Orientation {
+    /// Clockwise rotation in degrees applied to a sweep recorded at `heading_deg`
+    pub fn rotation_deg(&self, heading_deg: f64) -> f64 {
+        match self {
+            Self::NorthUp => heading_deg,
+            Self::HeadUp => 0.0,
+        }
+    }
+
+    /// Direction of the image top relative to north, in degrees clockwise
+    pub fn top_deg(&self, heading_deg: f64) -> f64 {
+        match self {
+            Self::NorthUp => 0.0,
+            Self::HeadUp => heading_deg,
+        }
+    }
+}
+
+/// Where the heading of each sweep comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeadingSource {
+    /// The same heading in degrees for every sweep
+    Constant(f64),
+    /// The heading column of the capture CSV, see
+    /// [`CsvFormat::heading`](crate::sweep::CsvFormat::heading)
+    Column,
+    /// A heading file matched by the filename timestamp of each capture
+    File(PathBuf),
+}
+
+/// Heading input and output orientation of a batch run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadingSettings {
+    pub source: HeadingSource,
+    pub orientation: Orientation,
+}
+
+/// Headings by time, read from a heading file.
+///
+/// Each line holds a time and a heading in degrees, separated by a comma,
+/// semicolon or tab. Times are read like capture filenames, so
+/// `2024-01-01T12:00:00.5Z` and `20240101120000500` both work. A header
+/// line is skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadingTable {
+    /// `(unix seconds, heading in degrees)`, sorted by time
+    entries: Vec<(f64, f64)>,
+}
+
+impl HeadingTable {
+    /// Read a heading file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("reading {}", path.display()))
+    }
+
+    /// Parse the contents of a heading file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut parts = line.split([',', ';', '\t']);
+            let time = parts.next().and_then(|t| Timestamp::parse_filename(t.trim()));
+            let heading = parts.next().and_then(|h| h.trim().parse::<f64>().ok());
+            match (time, heading) {
+                (Some(time), Some(heading)) if heading.is_finite() => {
+                    entries.push((time.unix_seconds(), heading.rem_euclid(360.0)));
+                }
+                _ if index == 0 => {} // Header line
+                _ => return Err(anyhow!("line {}: expected a time and a heading", index + 1)),
+            }
+        }
+        if entries.is_empty() {
+            return Err(anyhow!("no headings"));
+        }
+        entries.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(HeadingTable { entries })
+    }
+
+    /// Heading at `time`, interpolated along the shorter arc between the
+    /// neighbouring entries. `None` when `time` is more than a minute
+    /// outside the file.
+    pub fn at(&self, time: &Timestamp) -> Option<f64> {
+        let t = time.unix_seconds();
+        let after = self.entries.partition_point(|&(et, _)| et <= t);
+        if after == 0 {
+            let (first_t, first) = self.entries[0];
+            return (first_t - t <= MAX_EXTRAPOLATION_SECS).then_some(first);
+        }
+        let (t0, h0) = self.entries[after - 1];
+        let Some(&(t1, h1)) = self.entries.get(after) else {
+            return (t - t0 <= MAX_EXTRAPOLATION_SECS).then_some(h0);
+        };
+        let frac = (t - t0) / (t1 - t0);
+        let turn = (h1 - h0 + 180.0).rem_euclid(360.0) - 180.0;
+        Some((h0 + turn * frac).rem_euclid(360.0))
+    }
+}
+
+/// A [`HeadingSource`] ready for lookups, with its heading file loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Headings {
+    Constant(f64),
+    Column,
+    Table(HeadingTable),
+}
+
+impl Headings {
+    pub fn load(source: &HeadingSource) -> Result<Self> {
+        Ok(match source {
+            HeadingSource::Constant(deg) => Self::Constant(*deg),
+            HeadingSource::Column => Self::Column,
+            HeadingSource::File(path) => Self::Table(HeadingTable::load(path)?),
+        })
+    }
+
+    /// Heading in degrees clockwise from north of the capture taken at
+    /// `timestamp`, whose heading column gave `recorded`.
+    pub fn heading(&self, timestamp: &str, recorded: Option<f64>) -> Result<f64> {
+        match self {
+            Self::Constant(deg) => Ok(*deg),
+            Self::Column => recorded.ok_or_else(|| anyhow!("no heading column in capture {}", timestamp)),
+            Self::Table(table) => {
+                let time = Timestamp::parse_filename(timestamp)
+                    .ok_or_else(|| anyhow!("no timestamp in filename {}", timestamp))?;
+                table
+                    .at(&time)
+                    .ok_or_else(|| anyhow!("no heading for {} in the heading file", time))
+            }
+        }
+    }
+}
+
+/// Circular mean of headings in degrees, `None` when there are none or they
+/// cancel out.
+pub fn mean_heading(headings: impl IntoIterator<Item = f64>) -> Option<f64> {
+    let (sin, cos) = headings
+        .into_iter()
+        .map(|h| h.to_radians().sin_cos())
+        .fold((0.0, 0.0), |(s, c), (hs, hc)| (s + hs, c + hc));
+    (sin.hypot(cos) > 1e-9).then(|| sin.atan2(cos).to_degrees().rem_euclid(360.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(table: &HeadingTable, stem: &str) -> Option<f64> {
+        table.at(&Timestamp::parse_filename(stem).unwrap())
+    }
+
+    #[test]
+    fn heading_file_interpolates_across_north() {
+        let table = HeadingTable::parse("time,heading\n20240101120000,350\n2024-01-01T12:00:10Z,10\n").unwrap();
+        assert_eq!(at(&table, "20240101120000"), Some(350.0));
+        assert_eq!(at(&table, "20240101120005"), Some(0.0));
+        assert_eq!(at(&table, "20240101120007500"), Some(5.0));
+    }
+
+    #[test]
+    fn heading_file_extrapolates_up_to_a_minute() {
+        let table = HeadingTable::parse("20240101120000;90\n20240101120010;100\n").unwrap();
+        assert_eq!(at(&table, "20240101115930"), Some(90.0));
+        assert_eq!(at(&table, "20240101120110"), Some(100.0));
+        assert_eq!(at(&table, "20240101115859"), None);
+        assert_eq!(at(&table, "20240101120111"), None);
+    }
+
+    #[test]
+    fn heading_file_errors() {
+        assert!(HeadingTable::parse("time,heading\n").is_err());
+        let error = HeadingTable::parse("20240101120000,90\nsoon,100\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected a time and a heading");
+    }
+
+    #[test]
+    fn mean_heading_wraps() {
+        let mean = mean_heading([350.0, 20.0]).unwrap();
+        assert!((mean - 5.0).abs() < 1e-9, "{mean}");
+        assert_eq!(mean_heading([90.0, 270.0]), None);
+        assert_eq!(mean_heading([]), None);
+    }
+
+    #[test]
+    fn orientation_rotation_and_top() {
+        assert_eq!(Orientation::NorthUp.rotation_deg(30.0), 30.0);
+        assert_eq!(Orientation::NorthUp.top_deg(30.0), 0.0);
+        assert_eq!(Orientation::HeadUp.rotation_deg(30.0), 0.0);
+        assert_eq!(Orientation::HeadUp.top_deg(30.0), 30.0);
+        assert!(Headings::Column.heading("20240101120000", None).is_err());
+        assert_eq!(Headings::Column.heading("20240101120000", Some(12.0)).unwrap(), 12.0);
+    }
+}
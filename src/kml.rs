@@ -0,0 +1,115 @@
+//! KMZ export of rendered images as Google Earth ground overlays
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::geo::LatLonBox;
+use crate::timestamp::Timestamp;
+
+/// One rendered image placed on the globe.
+#[derive(Debug, Clone)]
+pub struct GroundOverlay {
+    /// PNG to embed
+    pub image: PathBuf,
+    /// Name shown in the Google Earth places panel
+    pub name: String,
+    /// Capture time for the time slider
+    pub time: Option<Timestamp>,
+    pub bounds: LatLonBox,
+}
+
+/// Escape text for use in XML content.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// KML document referencing each image as `images/<file name>`.
+fn document(title: &str, overlays: &[GroundOverlay]) -> String {
+    let mut kml = String::new();
+    kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    kml.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+    kml.push_str(&format!("  <name>{}</name>\n", escape(title)));
+    for overlay in overlays {
+        let file_name = overlay.image.file_name().and_then(|n| n.to_str()).unwrap_or("image.png");
+        let b = &overlay.bounds;
+        kml.push_str("  <GroundOverlay>\n");
+        kml.push_str(&format!("    <name>{}</name>\n", escape(&overlay.name)));
+        if let Some(time) = &overlay.time {
+            kml.push_str(&format!("    <TimeStamp><when>{}</when></TimeStamp>\n", time.to_rfc3339()));
+        }
+        kml.push_str(&format!("    <Icon><href>images/{}</href></Icon>\n", escape(file_name)));
+        kml.push_str("    <LatLonBox>\n");
+        kml.push_str(&format!("      <north>{:.8}</north>\n", b.north));
+        kml.push_str(&format!("      <south>{:.8}</south>\n", b.south));
+        kml.push_str(&format!("      <east>{:.8}</east>\n", b.east));
+        kml.push_str(&format!("      <west>{:.8}</west>\n", b.west));
+        kml.push_str(&format!("      <rotation>{:.4}</rotation>\n", b.rotation));
+        kml.push_str("    </LatLonBox>\n");
+        kml.push_str("  </GroundOverlay>\n");
+    }
+    kml.push_str("</Document>\n</kml>\n");
+    kml
+}
+
+/// Write a KMZ with `doc.kml` and every overlay image.
+///
+/// Images are stored uncompressed since PNGs are already compressed. A
+/// partially written file is removed on error.
+pub fn write_kmz(title: &str, overlays: &[GroundOverlay], output: &Path) -> Result<()> {
+    let result = write_kmz_inner(title, overlays, output);
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+fn write_kmz_inner(title: &str, overlays: &[GroundOverlay], output: &Path) -> Result<()> {
+    let file = File::create(output).with_context(|| format!("creating {}", output.display()))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    // Google Earth reads the first .kml entry as the document
+    zip.start_file("doc.kml", options)?;
+    zip.write_all(document(title, overlays).as_bytes())?;
+
+    for overlay in overlays {
+        let file_name = overlay.image.file_name().and_then(|n| n.to_str()).unwrap_or("image.png");
+        let data = fs::read(&overlay.image)
+            .with_context(|| format!("reading {}", overlay.image.display()))?;
+        zip.start_file(format!("images/{file_name}"), options)?;
+        zip.write_all(&data)?;
+    }
+
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document_lists_overlays() {
+        let overlay = GroundOverlay {
+            image: PathBuf::from("out/20240101120000_60_3.png"),
+            name: "Rings & <bearings>".to_string(),
+            time: Timestamp::parse_filename("20240101120000"),
+            bounds: LatLonBox { north: 34.5, south: 33.5, east: -80.5, west: -81.5, rotation: -90.0 },
+        };
+        let kml = document("capture", &[overlay]);
+        assert!(kml.contains("<name>Rings &amp; &lt;bearings&gt;</name>"));
+        assert!(kml.contains("<TimeStamp><when>2024-01-01T12:00:00.000Z</when></TimeStamp>"));
+        assert!(kml.contains("<Icon><href>images/20240101120000_60_3.png</href></Icon>"));
+        assert!(kml.contains("<north>34.50000000</north>"));
+        assert!(kml.contains("<west>-81.50000000</west>"));
+        assert!(kml.contains("<rotation>-90.0000</rotation>"));
+    }
+}
@@ -0,0 +1,300 @@
+//! Colorbar legend and metadata caption for self-describing images
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use image::{Rgba, RgbaImage};
+
+use crate::overlay::format_range;
+use crate::render::{Shader, Transfer};
+use crate::sweep::Sweep;
+use crate::timestamp::Timestamp;
+use crate::{draw, font};
+
+/// Where the legend is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegendPlacement {
+    /// Below the disk on a taller canvas, leaving the data untouched
+    Extend,
+    /// In the empty corners of the square image
+    Corner,
+}
+
+impl FromStr for LegendPlacement {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "extend" | "extended" => Ok(Self::Extend),
+            "corner" => Ok(Self::Corner),
+            _ => Err(anyhow!("Unknown legend placement: {s}")),
+        }
+    }
+}
+
+/// Contents and placement of the legend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegendOptions {
+    pub placement: LegendPlacement,
+    /// Colorbar with tick values from the normalization range
+    pub colorbar: bool,
+    /// Timestamp, gain code and range setting
+    pub caption: bool,
+    /// Text and tick color
+    pub text_color: [u8; 3],
+}
+
+impl Default for LegendOptions {
+    fn default() -> Self {
+        LegendOptions {
+            placement: LegendPlacement::Extend,
+            colorbar: true,
+            caption: true,
+            text_color: [255, 255, 255],
+        }
+    }
+}
+
+/// Caption lines for a sweep: the capture time and rotation number, then
+/// gain and range.
+///
+/// The time is shown as recorded in the filename, in the recorder's clock.
+pub fn caption(sweep: &Sweep) -> Vec<String> {
+    let mut time = Timestamp::parse_filename(&sweep.timestamp)
+        .map(|ts| ts.to_string())
+        .unwrap_or_else(|| sweep.timestamp.clone());
+    if let Some(rotation) = sweep.rotation {
+        time = format!("{time}  #{rotation}");
+    }
+    vec![time, format!("Gain {}  Range {}", sweep.gain, sweep.range_setting)]
+}
+
+/// Evenly spaced round tick values within `[min, max]`, about `target` of them.
+fn ticks(min: f32, max: f32, target: u32) -> Vec<f64> {
+    let (min, max) = (min as f64, max as f64);
+    let span = max - min;
+    if !span.is_finite() || span <= 0.0 || target < 2 {
+        return Vec::new();
+    }
+    let raw = span / (target - 1) as f64;
+    let mag = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * mag)
+        .min_by(|a, b| (a - raw).abs().total_cmp(&(b - raw).abs()))
+        .unwrap_or(mag);
+
+    let mut ticks = Vec::new();
+    let mut v = (min / step).ceil() * step;
+    while v <= max + step * 1e-6 {
+        ticks.push(v);
+        v += step;
+    }
+    ticks
+}
+
+/// Round tick values for a colorbar whose positions follow `transfer`.
+///
+/// A nonlinear transfer squeezes evenly spaced values into one end of the
+/// bar, so its ticks are instead taken at even bar positions and rounded to
+/// two significant digits.
+fn transfer_ticks(min: f32, max: f32, transfer: Transfer, target: u32) -> Vec<f64> {
+    if transfer == Transfer::Linear {
+        return ticks(min, max, target);
+    }
+    let (min, span) = (min as f64, (max - min) as f64);
+    if !span.is_finite() || span <= 0.0 || target < 2 {
+        return Vec::new();
+    }
+    let mut ticks: Vec<f64> = (0..target)
+        .map(|i| {
+            let v = min + transfer.invert(i as f32 / (target - 1) as f32) as f64 * span;
+            if v == 0.0 {
+                return v;
+            }
+            let mag = 10f64.powf(v.abs().log10().floor() - 1.0);
+            let rounded = (v / mag).round() * mag;
+            // The top tick must stay on the bar
+            if rounded > min + span { (v / mag).floor() * mag } else { rounded }
+        })
+        .collect();
+    ticks.dedup();
+    ticks
+}
+
+/// Horizontal colorbar with ticks and values underneath.
+///
+/// `(x, y)` is the top-left corner of the bar. Returns the height used.
+#[allow(clippy::too_many_arguments)]
+fn draw_colorbar(
+    img: &mut RgbaImage,
+    x: i64,
+    y: i64,
+    width: u32,
+    height: u32,
+    shader: &Shader,
+    scale: u32,
+    color: Rgba<u8>,
+    halo: Rgba<u8>,
+) -> u32 {
+    for i in 0..width {
+        let color = Rgba(shader.at_position(i as f32 / (width - 1).max(1) as f32));
+        for j in 0..height {
+            draw::blend(img, x + i as i64, y + j as i64, color, 1.0);
+        }
+    }
+    // Thin frame so the ends of the bar stay visible on dark backgrounds
+    let (x1, y1) = (x + width as i64, y + height as i64);
+    for i in x - 1..=x1 {
+        draw::blend(img, i, y - 1, halo, 1.0);
+        draw::blend(img, i, y1, halo, 1.0);
+    }
+    for j in y..y1 {
+        draw::blend(img, x - 1, j, halo, 1.0);
+        draw::blend(img, x1, j, halo, 1.0);
+    }
+
+    let char_h = font::text_height(scale);
+    let tick_len = char_h / 2;
+    let label_y = y1 + (tick_len + scale) as i64;
+    let (min, max) = shader.range();
+    let transfer = shader.transfer();
+    let span = (max - min) as f64;
+    // Right edge of the last label, so crowded labels are skipped
+    let mut label_end = i64::MIN;
+    for v in transfer_ticks(min, max, transfer, 5) {
+        let pos = transfer.apply(((v - min as f64) / span) as f32) as f64;
+        let tx = x + (pos * (width - 1) as f64).round() as i64;
+        for j in 0..tick_len as i64 {
+            for k in 0..scale as i64 {
+                draw::blend(img, tx + k - scale as i64 / 2, y1 + 1 + j, color, 1.0);
+            }
+        }
+        let label = format_range(v);
+        let w = font::text_width(&label, scale) as i64;
+        let lx = (tx - w / 2).clamp(scale as i64, img.width() as i64 - w - scale as i64);
+        if lx < label_end {
+            continue;
+        }
+        label_end = lx + w + font::text_width(" ", scale) as i64;
+        draw::text_with_halo(img, lx, label_y, &label, scale, color, halo);
+    }
+    height + tick_len + scale + char_h
+}
+
+/// Add the legend to a rendered image.
+///
+/// `shader` gives the colors, echo range and transfer of the colorbar, and
+/// `caption` the lines from [`caption`]. With [`LegendPlacement::Extend`]
+/// the image grows downwards by a strip holding the legend; otherwise it is
+/// drawn into the corners of the original canvas.
+pub fn apply_legend(img: RgbaImage, shader: &Shader, caption: &[String], opts: &LegendOptions) -> RgbaImage {
+    let size = img.width().min(img.height());
+    let scale = (size / 600).max(1);
+    let char_h = font::text_height(scale);
+    let line_h = char_h * 3 / 2;
+    let [r, g, b] = opts.text_color;
+    let color = Rgba([r, g, b, 255]);
+    let halo = Rgba([0, 0, 0, 128]);
+    let colorbar = opts.colorbar && !shader.is_empty();
+    let caption = if opts.caption { caption } else { &[] };
+
+    match opts.placement {
+        LegendPlacement::Extend => {
+            let pad = char_h * 2;
+            let bar_h = char_h * 2;
+            let bar_block = if colorbar { bar_h + char_h / 2 + scale + char_h + pad } else { 0 };
+            let caption_block = if caption.is_empty() { 0 } else { line_h * caption.len() as u32 + pad };
+            if bar_block + caption_block == 0 {
+                return img;
+            }
+
+            let mut out = RgbaImage::new(img.width(), img.height() + pad + bar_block + caption_block);
+            image::imageops::replace(&mut out, &img, 0, 0);
+            let mut y = (img.height() + pad) as i64;
+            if colorbar {
+                let width = img.width() * 7 / 10;
+                let x = ((img.width() - width) / 2) as i64;
+                y += (draw_colorbar(&mut out, x, y, width, bar_h, shader, scale, color, halo) + pad) as i64;
+            }
+            for line in caption {
+                let w = font::text_width(line, scale) as i64;
+                draw::text_with_halo(&mut out, (img.width() as i64 - w) / 2, y, line, scale, color, halo);
+                y += line_h as i64;
+            }
+            out
+        }
+        LegendPlacement::Corner => {
+            let mut out = img;
+            let margin = char_h as i64;
+            // Caption in the top-left corner
+            for (i, line) in caption.iter().enumerate() {
+                draw::text_with_halo(&mut out, margin, margin + (i as u32 * line_h) as i64, line, scale, color, halo);
+            }
+            // Colorbar in the bottom-right corner
+            if colorbar {
+                let width = size / 5;
+                let bar_h = char_h * 3 / 2;
+                let height = bar_h + char_h / 2 + scale + char_h;
+                let x = out.width() as i64 - margin - width as i64;
+                let y = out.height() as i64 - margin - height as i64;
+                draw_colorbar(&mut out, x, y, width, bar_h, shader, scale, color, halo);
+            }
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placement_parsing() {
+        assert_eq!("Extended".parse::<LegendPlacement>().unwrap(), LegendPlacement::Extend);
+        assert_eq!("corner".parse::<LegendPlacement>().unwrap(), LegendPlacement::Corner);
+        assert!("below".parse::<LegendPlacement>().is_err());
+    }
+
+    #[test]
+    fn caption_shows_the_recorded_time() {
+        let mut sweep = Sweep {
+            angles: Vec::new(),
+            bins: Vec::new(),
+            range_setting: 3,
+            gain: 60,
+            scale: None,
+            timestamp: "20240101120000123".to_string(),
+            rotation: None,
+            heading: None,
+        };
+        assert_eq!(caption(&sweep), ["2024-01-01 12:00:00.123", "Gain 60  Range 3"]);
+        sweep.timestamp = "capture".to_string();
+        sweep.rotation = Some(2);
+        assert_eq!(caption(&sweep)[0], "capture  #2");
+    }
+
+    #[test]
+    fn ticks_are_round_values() {
+        assert_eq!(ticks(0.0, 255.0, 6), [0.0, 50.0, 100.0, 150.0, 200.0, 250.0]);
+        assert_eq!(ticks(10.0, 95.0, 5), [20.0, 40.0, 60.0, 80.0]);
+        assert!(ticks(5.0, 5.0, 5).is_empty());
+        assert!(ticks(0.0, f32::NAN, 5).is_empty());
+    }
+
+    #[test]
+    fn nonlinear_ticks_follow_the_bar() {
+        let close = |actual: Vec<f64>, expected: &[f64]| {
+            assert_eq!(actual.len(), expected.len(), "{actual:?}");
+            let near = actual.iter().zip(expected).all(|(a, e)| (a - e).abs() <= 1e-6 * e.abs().max(1.0));
+            assert!(near, "{actual:?} != {expected:?}");
+        };
+        // Bar positions 0, 1/3, 2/3 and 1 are 30, 20, 10 and 0 dB below the top
+        close(transfer_ticks(0.0, 100.0, Transfer::Decibel { range_db: 30.0 }, 4), &[0.0, 1.0, 10.0, 100.0]);
+        // The middle of the bar is at 100 / sqrt(2), rounded to two digits
+        close(transfer_ticks(0.0, 100.0, Transfer::Gamma(2.0), 3), &[0.0, 71.0, 100.0]);
+        // Rounding never puts the top tick past the end of the bar
+        close(transfer_ticks(0.0, 96.6, Transfer::Gamma(1.0), 2), &[0.0, 96.0]);
+        assert_eq!(transfer_ticks(0.0, 255.0, Transfer::Linear, 6), ticks(0.0, 255.0, 6));
+    }
+}
@@ -0,0 +1,49 @@
+//! Radar Plotter - Furuno CSV to PPI rendering
+//!
+//! The pipeline is split into three stages that can be used on their own:
+//!
+//! 1. [`read_csv`] parses a capture into a [`Sweep`]; [`read_csv_with`] takes a
+//!    [`CsvFormat`] for other column layouts
+//! 2. [`regularize`] resamples the sweep onto a fixed [`PolarGrid`]
+//! 3. [`render_png`] draws the grid as a transparent PPI image
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use radar_plotter::{read_csv, regularize, render_png, GridOptions, RenderOptions};
+//!
+//! let sweep = read_csv(Path::new("capture/20240101120000.csv"))?;
+//! let grid = regularize(&sweep, &GridOptions::default());
+//! let img = render_png(&grid, &RenderOptions::default())?;
+//! img.save(sweep.output_name())?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! [`processing::process_folders`] runs the full pipeline over queued folders
+//! in parallel and is what the GUI and CLI use.
+
+pub mod animation;
+pub mod colormap;
+pub mod draw;
+pub mod font;
+pub mod geo;
+pub mod grid;
+pub mod heading;
+pub mod kml;
+pub mod legend;
+pub mod lut;
+pub mod manifest;
+pub mod overlay;
+pub mod processing;
+pub mod queue;
+pub mod render;
+pub mod sweep;
+pub mod timestamp;
+pub mod validation;
+pub mod viewer;
+pub mod watch;
+
+pub use colormap::CMap;
+pub use grid::{regularize, GridOptions, PolarGrid};
+pub use lut::{LutCache, PolarLut};
+pub use render::{render_png, render_with_lut, AlphaRamp, Interpolation, Normalization, RenderOptions, Transfer};
+pub use sweep::{read_csv, read_csv_with, CsvFormat, Sweep};
@@ -0,0 +1,182 @@
+//! Precomputed pixel to polar-grid mapping
+//!
+//! The mapping from output pixels to pulse and bin indices only depends on
+//! the image size and the grid shape, so it is computed once per folder and
+//! shared by all workers. Pixel bearings are kept to a fraction of a pulse,
+//! so the same table serves images turned by any angle.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+
+/// Marker for pixels outside the PPI disk
+const OUTSIDE: u32 = u32::MAX;
+
+/// Bearings are stored in units of a revolution over this
+const FULL_TURN: f64 = (1u64 << 32) as f64;
+
+/// Bearing in radians clockwise from the top as a fraction of a revolution,
+/// in units of 1 / 2^32.
+fn turn_fraction(theta: f64) -> u32 {
+    // A full turn wraps to 0
+    ((theta / (2.0 * PI)).rem_euclid(1.0) * FULL_TURN) as u64 as u32
+}
+
+/// Lookup table from pixel index to flat grid index `pulse * n_bins + bin`.
+#[derive(Debug, Clone)]
+pub struct PolarLut {
+    size: u32,
+    pulses: usize,
+    n_bins: usize,
+    /// Range bin of each pixel, [`OUTSIDE`] off the disk
+    bins: Vec<u32>,
+    /// Bearing of each pixel, see [`turn_fraction`]
+    bearings: Vec<u32>,
+}
+
+impl PolarLut {
+    /// Build the table for a `size` x `size` image of a `pulses` x `n_bins` grid.
+    ///
+    /// Fails when the grid has too many pulses or bins for 32-bit table entries.
+    pub fn new(size: u32, pulses: usize, n_bins: usize) -> Result<Self> {
+        if pulses > u32::MAX as usize || n_bins >= OUTSIDE as usize {
+            return Err(anyhow!("{pulses}x{n_bins} grid is too large for a lookup table"));
+        }
+        let cx = size as f64 / 2.0;
+        let cy = size as f64 / 2.0;
+        let radius = cx.min(cy);
+
+        let pixels = size as usize * size as usize;
+        let mut bins = Vec::with_capacity(pixels);
+        let mut bearings = Vec::with_capacity(pixels);
+        for y in 0..size {
+            for x in 0..size {
+                let dx = x as f64 + 0.5 - cx;
+                let dy = cy - (y as f64 + 0.5);
+                let r_norm = (dx * dx + dy * dy).sqrt() / radius;
+                let bin_idx = (r_norm * n_bins as f64).floor() as usize;
+                if r_norm > 1.0 || pulses == 0 || bin_idx >= n_bins {
+                    bins.push(OUTSIDE);
+                    bearings.push(0);
+                    continue;
+                }
+                bins.push(bin_idx as u32);
+                bearings.push(turn_fraction(dx.atan2(dy)));
+            }
+        }
+
+        Ok(PolarLut { size, pulses, n_bins, bins, bearings })
+    }
+
+    /// Image width and height in pixels.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Whether the table was built for this geometry.
+    pub fn matches(&self, size: u32, pulses: usize, n_bins: usize) -> bool {
+        self.size == size && self.pulses == pulses && self.n_bins == n_bins
+    }
+
+    /// Flat grid index for each pixel in row-major order of an image turned
+    /// clockwise by `rotation` radians, `None` outside the disk.
+    pub fn indices(&self, rotation: f64) -> impl Iterator<Item = Option<usize>> + '_ {
+        let turn = turn_fraction(rotation);
+        self.bins.iter().zip(&self.bearings).map(move |(&bin, &bearing)| {
+            if bin == OUTSIDE {
+                return None;
+            }
+            let pulse = (bearing.wrapping_sub(turn) as u64 * self.pulses as u64) >> 32;
+            Some(pulse as usize * self.n_bins + bin as usize)
+        })
+    }
+}
+
+/// Thread-safe cache of lookup tables for one image size and pulse count,
+/// keyed by the number of range bins.
+#[derive(Debug)]
+pub struct LutCache {
+    size: u32,
+    pulses: usize,
+    tables: Mutex<HashMap<usize, Arc<PolarLut>>>,
+}
+
+impl LutCache {
+    pub fn new(size: u32, pulses: usize) -> Self {
+        LutCache {
+            size,
+            pulses,
+            tables: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Table for grids with `n_bins` range bins, built on first use.
+    pub fn get(&self, n_bins: usize) -> Result<Arc<PolarLut>> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(lut) = tables.get(&n_bins) {
+            return Ok(lut.clone());
+        }
+        let lut = Arc::new(PolarLut::new(self.size, self.pulses, n_bins)?);
+        tables.insert(n_bins, lut.clone());
+        Ok(lut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_map_to_pulse_and_bin() {
+        // 4 x 4 pixels, four 90° pulses of two bins
+        let lut = PolarLut::new(4, 4, 2).unwrap();
+        let indices: Vec<Option<usize>> = lut.indices(0.0).collect();
+        let at = |x: usize, y: usize| indices[y * 4 + x];
+        // Corners are outside the disk
+        assert_eq!(at(0, 0), None);
+        assert_eq!(at(3, 3), None);
+        // Around the centre, clockwise from north-east: pulses 0 to 3, inner bin
+        assert_eq!(at(2, 1), Some(0));
+        assert_eq!(at(2, 2), Some(2));
+        assert_eq!(at(1, 2), Some(4));
+        assert_eq!(at(1, 1), Some(6));
+        // Top edge, right of centre: pulse 0, outer bin
+        assert_eq!(at(2, 0), Some(1));
+    }
+
+    #[test]
+    fn rotation_offsets_the_pulse() {
+        let lut = PolarLut::new(4, 4, 2).unwrap();
+        let pulses_at = |rotation: f64| -> Vec<usize> {
+            let indices: Vec<Option<usize>> = lut.indices(rotation).collect();
+            [(2, 1), (2, 2), (1, 2), (1, 1)].iter().map(|&(x, y)| indices[y * 4 + x].unwrap() / 2).collect()
+        };
+        // The inner pixels sit at 45°, 135°, 225° and 315°
+        assert_eq!(pulses_at(0.0), [0, 1, 2, 3]);
+        assert_eq!(pulses_at(40f64.to_radians()), [0, 1, 2, 3]);
+        assert_eq!(pulses_at(50f64.to_radians()), [3, 0, 1, 2]);
+        assert_eq!(pulses_at(-50f64.to_radians()), [1, 2, 3, 0]);
+        assert_eq!(pulses_at(2.0 * PI + 50f64.to_radians()), [3, 0, 1, 2]);
+    }
+
+    #[test]
+    fn cache_shares_tables() {
+        let cache = LutCache::new(16, 8);
+        let a = cache.get(4).unwrap();
+        assert!(Arc::ptr_eq(&a, &cache.get(4).unwrap()));
+        assert!(a.matches(16, 8, 4));
+        assert!(!cache.get(5).unwrap().matches(16, 8, 4));
+    }
+
+    #[test]
+    fn oversized_grids_are_rejected() {
+        // Bins up to 2^32 - 2 fit next to the outside marker
+        assert!(PolarLut::new(4, 1 << 16, 1 << 16).is_ok());
+        assert!(PolarLut::new(4, 2, OUTSIDE as usize - 1).is_ok());
+        assert!(PolarLut::new(4, 2, OUTSIDE as usize).is_err());
+        assert!(PolarLut::new(4, 1 << 33, 2).is_err());
+        assert!(LutCache::new(4, 2).get(usize::MAX).is_err());
+    }
+}
@@ -4,9 +4,8 @@
 
 slint::include_modules!();
 
-mod processing;
-mod queue;
 mod config;
+mod viewer_window;
 
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -14,7 +13,8 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc};
 use std::thread;
 
-use slint::{ModelRc, SharedString, VecModel};
+use radar_plotter::{colormap, processing, queue, CMap};
+use slint::{ModelRc, Rgba8Pixel, SharedPixelBuffer, SharedString, VecModel};
 
 fn main() -> Result<(), slint::PlatformError> {
     let ui = AppWindow::new()?;
@@ -26,13 +26,11 @@ fn main() -> Result<(), slint::PlatformError> {
     // Keep timer alive by storing it in shared state
     let progress_timer: Rc<RefCell<Option<slint::Timer>>> = Rc::new(RefCell::new(None));
     
+    show_custom_colormaps(&ui);
+
     // Load saved settings
     if let Ok(settings) = config::load_settings() {
-        ui.set_pulses(settings.pulses);
-        ui.set_gap_deg(settings.gap_deg as f32);
-        ui.set_image_size(settings.image_size);
-        ui.set_colormap(settings.colormap.into());
-        ui.set_jobs(settings.jobs);
+        apply_settings(&ui, &settings);
     }
 
     
@@ -67,6 +65,27 @@ fn main() -> Result<(), slint::PlatformError> {
         });
     }
     
+    // Sweep viewer callback
+    {
+        let viewer: Rc<RefCell<Option<ViewerWindow>>> = Rc::new(RefCell::new(None));
+        ui.on_open_viewer(move || {
+            let mut viewer = viewer.borrow_mut();
+            if viewer.is_none() {
+                match viewer_window::create() {
+                    Ok(window) => *viewer = Some(window),
+                    Err(e) => {
+                        eprintln!("Failed to open viewer: {}", e);
+                        return;
+                    }
+                }
+            }
+            if let Some(window) = viewer.as_ref() {
+                let _ = window.show();
+                window.invoke_open_file();
+            }
+        });
+    }
+    
     // Remove folder callback
     {
         let ui_weak = ui.as_weak();
@@ -124,15 +143,10 @@ fn main() -> Result<(), slint::PlatformError> {
     }
     
     {
-        ui.on_settings_changed(move |pulses, gap_deg, image_size, colormap, jobs| {
-            let settings = config::Settings {
-                pulses,
-                gap_deg: gap_deg as f64,
-                image_size,
-                colormap: colormap.to_string(),
-                jobs,
-            };
-            let _ = config::save_settings(&settings);
+        let ui_weak = ui.as_weak();
+        ui.on_settings_changed(move || {
+            let ui = ui_weak.unwrap();
+            let _ = config::save_settings(&settings_from_ui(&ui));
         });
     }
 
@@ -157,12 +171,12 @@ fn main() -> Result<(), slint::PlatformError> {
             stop_flag.store(false, Ordering::Relaxed);
             
             // Get settings
-            let settings = processing::ProcessingSettings {
-                pulses: ui.get_pulses() as usize,
-                gap_deg: ui.get_gap_deg() as f64,
-                size: ui.get_image_size() as u32,
-                colormap: ui.get_colormap().to_string(),
-                jobs: ui.get_jobs() as usize,
+            let settings = match settings_from_ui(&ui).processing_settings() {
+                Ok(settings) => settings,
+                Err(e) => {
+                    ui.set_status_text(SharedString::from(format!("Invalid settings: {:#}", e)));
+                    return;
+                }
             };
 
             
@@ -183,6 +197,8 @@ fn main() -> Result<(), slint::PlatformError> {
             ui.set_files_completed(0);
             ui.set_files_total(0);
             ui.set_overall_progress(0.0);
+            ui.set_preview_image(slint::Image::default());
+            ui.set_preview_caption("".into());
             
             // Reset progress for all folders
             {
@@ -206,6 +222,7 @@ fn main() -> Result<(), slint::PlatformError> {
             let ui_weak_poll = ui.as_weak();
             let folders_poll = folders.clone();
             let processing_handle_poll = processing_handle.clone();
+            let mut files_flagged_total = 0;
             
             let timer = slint::Timer::default();
             timer.start(
@@ -262,7 +279,8 @@ fn main() -> Result<(), slint::PlatformError> {
                                     ui.set_eta_text(SharedString::from(format!("{:02}:{:02}", eta_mins, eta_secs_rem)));
                                 }
                             }
-                            processing::ProgressUpdate::FolderCompleted { folder_index } => {
+                            processing::ProgressUpdate::FolderCompleted { folder_index, files_flagged, .. } => {
+                                files_flagged_total += files_flagged;
                                 let mut folders_mut = folders_poll.borrow_mut();
                                 if folder_index < folders_mut.len() {
                                     folders_mut[folder_index].status = queue::FolderStatus::Complete;
@@ -289,11 +307,62 @@ fn main() -> Result<(), slint::PlatformError> {
                                 drop(folders_mut);
                                 update_folder_model(&ui, &folders_poll.borrow());
                             }
+                            processing::ProgressUpdate::FolderWatching { folder_index } => {
+                                let mut folders_mut = folders_poll.borrow_mut();
+                                if folder_index < folders_mut.len() {
+                                    folders_mut[folder_index].status = queue::FolderStatus::Watching;
+                                    folders_mut[folder_index].progress = 1.0;
+                                }
+                                drop(folders_mut);
+                                ui.set_status_text("Watching for new files".into());
+                                update_folder_model(&ui, &folders_poll.borrow());
+                            }
+                            processing::ProgressUpdate::FileWatched { folder_index, file_name, error } => {
+                                let mut folders_mut = folders_poll.borrow_mut();
+                                if folder_index < folders_mut.len() {
+                                    match error {
+                                        None => folders_mut[folder_index].file_count += 1,
+                                        Some(error) => {
+                                            folders_mut[folder_index].error_message = Some(format!("{}: {}", file_name, error));
+                                        }
+                                    }
+                                }
+                                drop(folders_mut);
+                                ui.set_current_file(file_name.into());
+                                update_folder_model(&ui, &folders_poll.borrow());
+                            }
+                            processing::ProgressUpdate::Preview { folder_index, file_name, image } => {
+                                let buffer = SharedPixelBuffer::<Rgba8Pixel>::clone_from_slice(
+                                    image.as_raw(),
+                                    image.width(),
+                                    image.height(),
+                                );
+                                ui.set_preview_image(slint::Image::from_rgba8(buffer));
+                                let folder_name = folders_poll.borrow()
+                                    .get(folder_index)
+                                    .map(|f| f.name.clone())
+                                    .unwrap_or_default();
+                                ui.set_preview_caption(SharedString::from(format!("{} / {}", folder_name, file_name)));
+                            }
                             processing::ProgressUpdate::AllComplete => {
+                                // Watched folders are done once watching stops
+                                for folder in folders_poll.borrow_mut().iter_mut() {
+                                    if matches!(folder.status, queue::FolderStatus::Watching) {
+                                        folder.status = queue::FolderStatus::Complete;
+                                    }
+                                }
+                                update_folder_model(&ui, &folders_poll.borrow());
                                 ui.set_is_processing(false);
                                 ui.set_is_complete(true);
                                 ui.set_overall_progress(1.0);
-                                ui.set_status_text("Processing complete!".into());
+                                if files_flagged_total > 0 {
+                                    ui.set_status_text(SharedString::from(format!(
+                                        "Processing complete, {} files flagged (see validation_report.txt)",
+                                        files_flagged_total
+                                    )));
+                                } else {
+                                    ui.set_status_text("Processing complete!".into());
+                                }
                                 ui.set_eta_text("--:--".into());
                                 
                                 // Clean up handle
@@ -340,6 +409,160 @@ fn main() -> Result<(), slint::PlatformError> {
 
 }
 
+/// Push saved settings into the UI
+fn apply_settings(ui: &AppWindow, settings: &config::Settings) {
+    ui.set_pulses(settings.pulses);
+    ui.set_gap_deg(settings.gap_deg as f32);
+    ui.set_gap_fill(settings.gap_fill.clone().into());
+    ui.set_azimuth_offset(settings.azimuth_offset_deg.to_string().into());
+    ui.set_aggregation(settings.aggregation.clone().into());
+    ui.set_image_size(settings.image_size);
+    ui.set_interpolation(settings.interpolation.clone().into());
+    ui.set_antialias(settings.antialias);
+    ui.set_colormap(settings.colormap.clone().into());
+    ui.set_jobs(settings.jobs);
+    ui.set_normalization(settings.normalization.clone().into());
+    ui.set_norm_min(settings.norm_min as i32);
+    ui.set_norm_max(settings.norm_max as i32);
+    ui.set_norm_percentile(settings.norm_percentile as f32);
+    ui.set_transfer(settings.transfer.clone().into());
+    ui.set_transfer_db_range(settings.transfer_db_range as i32);
+    ui.set_transfer_gamma(settings.transfer_gamma as f32);
+    ui.set_noise_floor(settings.noise_floor as i32);
+    ui.set_ceiling(settings.ceiling as i32);
+    ui.set_alpha_ramp(settings.alpha_ramp);
+    ui.set_alpha_start((settings.alpha_start * 100.0).round() as i32);
+    ui.set_alpha_end((settings.alpha_end * 100.0).round() as i32);
+    ui.set_alpha_gamma(settings.alpha_gamma as f32);
+    ui.set_animation(settings.animation);
+    ui.set_animation_format(settings.animation_format.clone().into());
+    ui.set_frame_delay_ms(settings.frame_delay_ms);
+    ui.set_overlay(settings.overlay);
+    ui.set_overlay_mode(settings.overlay_mode.clone().into());
+    ui.set_overlay_rings(settings.overlay_rings);
+    ui.set_overlay_spoke_deg(settings.overlay_spoke_deg as i32);
+    ui.set_overlay_opacity(settings.overlay_opacity as f32);
+    ui.set_legend(settings.legend);
+    ui.set_legend_placement(settings.legend_placement.clone().into());
+    ui.set_legend_caption(settings.legend_caption);
+    ui.set_georef(settings.georef);
+    ui.set_radar_lat(settings.radar_lat.to_string().into());
+    ui.set_radar_lon(settings.radar_lon.to_string().into());
+    ui.set_radar_heading(settings.radar_heading_deg.to_string().into());
+    ui.set_heading_source(settings.heading_source.clone().into());
+    ui.set_vessel_heading(settings.vessel_heading_deg.to_string().into());
+    ui.set_heading_column(settings.heading_column.clone().into());
+    ui.set_heading_file(settings.heading_file.clone().into());
+    ui.set_orientation(settings.orientation.clone().into());
+    ui.set_meters_per_unit(settings.meters_per_unit.to_string().into());
+    ui.set_kmz(settings.kmz);
+    ui.set_resume(settings.resume.clone().into());
+    ui.set_watch(settings.watch);
+    ui.set_csv_delimiter(settings.csv_delimiter.clone().into());
+    ui.set_csv_header(settings.csv_header);
+    ui.set_csv_range_column(settings.csv_range_column.clone().into());
+    ui.set_csv_gain_column(settings.csv_gain_column.clone().into());
+    ui.set_csv_angle_column(settings.csv_angle_column.clone().into());
+    ui.set_ticks_per_rev(settings.ticks_per_rev);
+    ui.set_csv_echo_column(settings.csv_echo_column.clone().into());
+    ui.set_csv_status_column(settings.csv_status_column.clone().into());
+    ui.set_csv_scale_column(settings.csv_scale_column.clone().into());
+    ui.set_validation(settings.validation.clone().into());
+    ui.set_split_rotations(settings.split_rotations);
+}
+
+/// Read the current settings from the UI, keeping saved values that have no control
+fn settings_from_ui(ui: &AppWindow) -> config::Settings {
+    let saved = config::load_settings().unwrap_or_default();
+    config::Settings {
+        pulses: ui.get_pulses(),
+        gap_deg: ui.get_gap_deg() as f64,
+        gap_fill: ui.get_gap_fill().to_string(),
+        aggregation: ui.get_aggregation().to_string(),
+        image_size: ui.get_image_size(),
+        interpolation: ui.get_interpolation().to_string(),
+        antialias: ui.get_antialias(),
+        colormap: ui.get_colormap().to_string(),
+        jobs: ui.get_jobs(),
+        normalization: ui.get_normalization().to_string(),
+        norm_min: ui.get_norm_min() as f64,
+        norm_max: ui.get_norm_max() as f64,
+        norm_percentile: ui.get_norm_percentile() as f64,
+        transfer: ui.get_transfer().to_string(),
+        transfer_db_range: ui.get_transfer_db_range() as f64,
+        transfer_gamma: ui.get_transfer_gamma() as f64,
+        noise_floor: ui.get_noise_floor() as f64,
+        ceiling: ui.get_ceiling() as f64,
+        alpha_ramp: ui.get_alpha_ramp(),
+        alpha_start: ui.get_alpha_start() as f64 / 100.0,
+        alpha_end: ui.get_alpha_end() as f64 / 100.0,
+        alpha_gamma: ui.get_alpha_gamma() as f64,
+        animation: ui.get_animation(),
+        animation_format: ui.get_animation_format().to_string(),
+        frame_delay_ms: ui.get_frame_delay_ms(),
+        overlay: ui.get_overlay(),
+        overlay_mode: ui.get_overlay_mode().to_string(),
+        overlay_rings: ui.get_overlay_rings(),
+        overlay_spoke_deg: ui.get_overlay_spoke_deg() as f64,
+        overlay_opacity: ui.get_overlay_opacity() as f64,
+        legend: ui.get_legend(),
+        legend_placement: ui.get_legend_placement().to_string(),
+        legend_caption: ui.get_legend_caption(),
+        georef: ui.get_georef(),
+        // Coordinates are edited as text to keep full precision
+        radar_lat: ui.get_radar_lat().trim().parse().unwrap_or(saved.radar_lat),
+        radar_lon: ui.get_radar_lon().trim().parse().unwrap_or(saved.radar_lon),
+        radar_heading_deg: ui.get_radar_heading().trim().parse().unwrap_or(saved.radar_heading_deg),
+        azimuth_offset_deg: ui.get_azimuth_offset().trim().parse().unwrap_or(saved.azimuth_offset_deg),
+        heading_source: ui.get_heading_source().to_string(),
+        vessel_heading_deg: ui.get_vessel_heading().trim().parse().unwrap_or(saved.vessel_heading_deg),
+        heading_column: ui.get_heading_column().trim().to_string(),
+        heading_file: ui.get_heading_file().trim().to_string(),
+        orientation: ui.get_orientation().to_string(),
+        meters_per_unit: ui.get_meters_per_unit().trim().parse().unwrap_or(saved.meters_per_unit),
+        kmz: ui.get_kmz(),
+        resume: ui.get_resume().to_string(),
+        watch: ui.get_watch(),
+        csv_delimiter: ui.get_csv_delimiter().to_string(),
+        csv_header: ui.get_csv_header(),
+        csv_range_column: ui.get_csv_range_column().trim().to_string(),
+        csv_gain_column: ui.get_csv_gain_column().trim().to_string(),
+        csv_angle_column: ui.get_csv_angle_column().trim().to_string(),
+        csv_echo_column: ui.get_csv_echo_column().trim().to_string(),
+        csv_status_column: ui.get_csv_status_column().trim().to_string(),
+        csv_scale_column: ui.get_csv_scale_column().trim().to_string(),
+        ticks_per_rev: ui.get_ticks_per_rev(),
+        validation: ui.get_validation().to_string(),
+        split_rotations: ui.get_split_rotations(),
+        ..saved
+    }
+}
+
+/// List the colormap files of the user colormap folder in the settings panel
+fn show_custom_colormaps(ui: &AppWindow) {
+    let (cmaps, errors) = colormap::user_colormaps();
+    for e in errors {
+        eprintln!("Skipping colormap: {:#}", e);
+    }
+    const PREVIEW_WIDTH: u32 = 256;
+    let items: Vec<CustomColormap> = cmaps
+        .iter()
+        // A file named like a built-in map is never selected by name
+        .filter(|cmap| cmap.name().parse::<CMap>().is_err())
+        .map(|cmap| {
+            let pixels: Vec<u8> = (0..PREVIEW_WIDTH)
+                .flat_map(|i| cmap.eval(i as f64 / (PREVIEW_WIDTH - 1) as f64))
+                .collect();
+            let buffer = SharedPixelBuffer::<Rgba8Pixel>::clone_from_slice(&pixels, PREVIEW_WIDTH, 1);
+            CustomColormap {
+                name: cmap.name().into(),
+                preview: slint::Image::from_rgba8(buffer),
+            }
+        })
+        .collect();
+    ui.set_custom_colormaps(ModelRc::from(Rc::new(VecModel::from(items))));
+}
+
 /// Update the folder model in the UI from the internal state
 fn update_folder_model(ui: &AppWindow, folders: &[queue::FolderInfo]) {
     let items: Vec<FolderItem> = folders.iter().map(|f| {
@@ -352,6 +575,7 @@ fn update_folder_model(ui: &AppWindow, folders: &[queue::FolderInfo]) {
                 queue::FolderStatus::Processing => "processing".into(),
                 queue::FolderStatus::Complete => "complete".into(),
                 queue::FolderStatus::Error => "error".into(),
+                queue::FolderStatus::Watching => "watching".into(),
             },
             progress: f.progress,
             error_message: f.error_message.clone().unwrap_or_default().into(),
@@ -0,0 +1,217 @@
+//! Skipping of already-rendered files when a batch is restarted
+//!
+//! Two strategies are available. [`ResumeMode::Newer`] compares file times:
+//! a CSV is skipped when its PNG exists and is at least as new. This is cheap
+//! but does not notice changed settings. [`ResumeMode::Manifest`] keeps a
+//! manifest in the output folder recording a hash of the render settings and
+//! of every input file, so only files whose content or settings changed are
+//! rendered again.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Manifest filename inside the output folder
+pub const MANIFEST_NAME: &str = ".radar_plotter_manifest.json";
+
+/// How to decide that a CSV file does not need rendering again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResumeMode {
+    /// Render everything
+    #[default]
+    Off,
+    /// Skip when the output PNG exists and is newer than the CSV
+    Newer,
+    /// Skip when the manifest records the same settings and input hash
+    Manifest,
+}
+
+impl FromStr for ResumeMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "newer" => Ok(Self::Newer),
+            "manifest" => Ok(Self::Manifest),
+            _ => Err(anyhow!("Unknown resume mode: {s}")),
+        }
+    }
+}
+
+/// 64-bit FNV-1a hash.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Hash of a file's contents.
+pub fn hash_file(path: &Path) -> Result<u64> {
+    let data = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    Ok(fnv1a(&data))
+}
+
+/// What was written for one input file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// FNV-1a hash of the CSV file
+    pub input_hash: u64,
+    /// Images written, one per rotation when the file was split
+    pub frames: Vec<ManifestFrame>,
+}
+
+/// One image written for an input file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFrame {
+    /// Output image filename
+    pub output: String,
+    pub width: u32,
+    pub height: u32,
+    /// Range at the disk edge
+    pub range_max: f64,
+    /// Direction of the image top, degrees clockwise from north
+    #[serde(default)]
+    pub top_deg: f64,
+}
+
+/// Record of the files rendered into one output folder.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Hash of everything that affects the rendered images
+    pub settings_hash: u64,
+    /// Entries keyed by CSV filename
+    pub entries: HashMap<String, ManifestEntry>,
+    /// Folder maximum the entries were scaled by under per-folder normalization
+    #[serde(default)]
+    pub folder_max: Option<f32>,
+}
+
+impl Manifest {
+    /// Load the manifest of `output_dir`.
+    ///
+    /// A missing or unreadable manifest, or one written with different
+    /// settings, gives an empty manifest for `settings_hash`.
+    pub fn load(output_dir: &Path, settings_hash: u64) -> Self {
+        fs::read_to_string(output_dir.join(MANIFEST_NAME))
+            .ok()
+            .and_then(|text| serde_json::from_str::<Manifest>(&text).ok())
+            .filter(|m| m.settings_hash == settings_hash)
+            .unwrap_or(Manifest {
+                settings_hash,
+                ..Manifest::default()
+            })
+    }
+
+    /// Record the folder maximum of per-folder normalization, dropping all
+    /// entries when it differs from the one they were rendered with.
+    pub fn set_folder_max(&mut self, folder_max: f32) {
+        if self.folder_max != Some(folder_max) {
+            self.entries.clear();
+            self.folder_max = Some(folder_max);
+        }
+    }
+
+    /// Frames of `csv_name` if the input is unchanged and all outputs still exist.
+    pub fn lookup(&self, output_dir: &Path, csv_name: &str, input_hash: u64) -> Option<Vec<(PathBuf, &ManifestFrame)>> {
+        let entry = self.entries.get(csv_name)?;
+        if entry.input_hash != input_hash || entry.frames.is_empty() {
+            return None;
+        }
+        entry
+            .frames
+            .iter()
+            .map(|frame| {
+                let path = output_dir.join(&frame.output);
+                path.is_file().then_some((path, frame))
+            })
+            .collect()
+    }
+
+    pub fn insert(&mut self, csv_name: String, entry: ManifestEntry) {
+        self.entries.insert(csv_name, entry);
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Write serialized manifest `json` into `output_dir`, replacing the old one atomically.
+pub fn save(output_dir: &Path, json: &str) -> Result<()> {
+    let path = output_dir.join(MANIFEST_NAME);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json).with_context(|| format!("writing {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("radar_plotter_manifest_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(input_hash: u64, outputs: &[&str]) -> ManifestEntry {
+        ManifestEntry {
+            input_hash,
+            frames: outputs
+                .iter()
+                .map(|output| ManifestFrame {
+                    output: output.to_string(),
+                    width: 100,
+                    height: 100,
+                    range_max: 3.0,
+                    top_deg: 0.0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn fnv1a_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn lookup_needs_same_input_and_all_outputs() {
+        let dir = temp_dir("lookup");
+        fs::write(dir.join("a_r001.png"), b"").unwrap();
+        let mut manifest = Manifest::default();
+        manifest.insert("a.csv".to_string(), entry(7, &["a_r001.png", "a_r002.png"]));
+        manifest.insert("b.csv".to_string(), entry(7, &[]));
+
+        // One of the two rotations is missing
+        assert!(manifest.lookup(&dir, "a.csv", 7).is_none());
+        fs::write(dir.join("a_r002.png"), b"").unwrap();
+        let found: Vec<PathBuf> = manifest.lookup(&dir, "a.csv", 7).unwrap().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(found, [dir.join("a_r001.png"), dir.join("a_r002.png")]);
+
+        assert!(manifest.lookup(&dir, "a.csv", 8).is_none());
+        assert!(manifest.lookup(&dir, "b.csv", 7).is_none());
+        assert!(manifest.lookup(&dir, "c.csv", 7).is_none());
+    }
+
+    #[test]
+    fn load_discards_other_settings() {
+        let dir = temp_dir("load");
+        let mut manifest = Manifest { settings_hash: 1, ..Manifest::default() };
+        manifest.insert("a.csv".to_string(), entry(7, &["a.png"]));
+        save(&dir, &manifest.to_json().unwrap()).unwrap();
+
+        assert_eq!(Manifest::load(&dir, 1).entries, manifest.entries);
+        let other = Manifest::load(&dir, 2);
+        assert_eq!(other.settings_hash, 2);
+        assert!(other.entries.is_empty());
+    }
+}
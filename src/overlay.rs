@@ -0,0 +1,332 @@
+//! Range rings, bearing spokes and north marker drawn over PPI images
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Error, Result};
+use image::{Rgba, RgbaImage};
+
+use crate::{draw, font};
+
+/// Where the overlay ends up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayMode {
+    /// Drawn into every rendered image
+    Burn,
+    /// Written once per disk range as a transparent `overlay_<range>.png`,
+    /// named after the range at the image edge
+    Separate,
+}
+
+impl FromStr for OverlayMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "burn" => Ok(Self::Burn),
+            "separate" => Ok(Self::Separate),
+            _ => Err(anyhow!("Unknown overlay mode: {s}")),
+        }
+    }
+}
+
+/// Allowed angles between bearing spokes in degrees, besides 0 for none
+pub const SPOKE_DEG_RANGE: RangeInclusive<f64> = 1.0..=180.0;
+
+/// Appearance of the spatial reference overlay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayOptions {
+    /// Number of evenly spaced range rings out to the disk edge (0 = none)
+    pub rings: u32,
+    /// Angle between bearing spokes in degrees (0 = none), clamped to
+    /// [`SPOKE_DEG_RANGE`] when drawn
+    pub spoke_deg: f64,
+    /// Draw a north arrow at the top of the disk
+    pub north_marker: bool,
+    /// Label rings in range units and spokes in degrees
+    pub labels: bool,
+    /// Ring, spoke and marker color
+    pub line_color: [u8; 3],
+    /// Label color
+    pub label_color: [u8; 3],
+    /// Opacity of everything drawn, 0-1; values outside are clamped
+    pub opacity: f32,
+    /// Line width in pixels
+    pub line_width: f64,
+    pub mode: OverlayMode,
+}
+
+impl Default for OverlayOptions {
+    fn default() -> Self {
+        OverlayOptions {
+            rings: 4,
+            spoke_deg: 30.0,
+            north_marker: true,
+            labels: true,
+            line_color: [255, 255, 255],
+            label_color: [255, 255, 255],
+            opacity: 0.6,
+            line_width: 1.5,
+            mode: OverlayMode::Burn,
+        }
+    }
+}
+
+impl OverlayOptions {
+    /// Angle between bearing spokes within [`SPOKE_DEG_RANGE`], or `None`
+    /// when spokes are off.
+    pub fn spoke_spacing(&self) -> Option<f64> {
+        (self.spoke_deg > 0.0).then(|| self.spoke_deg.clamp(*SPOKE_DEG_RANGE.start(), *SPOKE_DEG_RANGE.end()))
+    }
+
+    /// Alpha of lines and labels.
+    fn alpha(&self) -> u8 {
+        // NaN casts to 0
+        (self.opacity.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+/// Parse a `RRGGBB` or `#RRGGBB` color.
+pub fn parse_hex_color(s: &str) -> Result<[u8; 3]> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(anyhow!("Invalid color: {s}"));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| anyhow!("Invalid color: {s}"));
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Format a label value without trailing zeros, e.g. `1.5` or `0.75`.
+pub(crate) fn format_range(v: f64) -> String {
+    let s = format!("{:.2}", v);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Top-left corner of a `w` x `h` label drawn at (x, y), moved inside a
+/// `size` x `size` image with room for its halo.
+fn inside(x: f64, y: f64, w: f64, h: f64, scale: u32, size: u32) -> (i64, i64) {
+    let margin = scale as f64 + 1.0;
+    let max = |extent: f64| (size as f64 - extent - margin).max(margin);
+    (x.clamp(margin, max(w)) as i64, y.clamp(margin, max(h)) as i64)
+}
+
+/// Draw the overlay onto `img`, whose disk edge is at `range_max` range units.
+pub fn draw_overlay(img: &mut RgbaImage, range_max: f64, opts: &OverlayOptions) {
+    let size = img.width().min(img.height());
+    let c = size as f64 / 2.0;
+    let radius = c;
+    let alpha = opts.alpha();
+    let [r, g, b] = opts.line_color;
+    let line_color = Rgba([r, g, b, alpha]);
+    let [r, g, b] = opts.label_color;
+    let label_color = Rgba([r, g, b, alpha]);
+    let halo = Rgba([0, 0, 0, alpha / 2]);
+    let scale = (size / 600).max(1);
+    let char_h = font::text_height(scale) as f64;
+
+    // Bearing spokes
+    let spoke_deg = opts.spoke_spacing();
+    if let Some(spoke_deg) = spoke_deg {
+        let n = (360.0 / spoke_deg).floor() as u32;
+        for i in 0..n {
+            let theta = (i as f64 * spoke_deg).to_radians();
+            let end = (c + radius * theta.sin(), c - radius * theta.cos());
+            draw::line(img, (c, c), end, opts.line_width, line_color);
+        }
+    }
+
+    // Range rings
+    for i in 1..=opts.rings {
+        let r = (radius * i as f64 / opts.rings as f64).min(radius - opts.line_width / 2.0);
+        draw::circle(img, c, c, r, opts.line_width, line_color);
+    }
+
+    // North marker
+    let marker = radius * 0.04;
+    if opts.north_marker {
+        draw_north_marker(img, 0.0, opts);
+    }
+
+    if !opts.labels {
+        return;
+    }
+
+    // Ring labels between the first two spokes, just outside each ring
+    let label_bearing = spoke_deg.map_or(45.0, |spoke_deg| (spoke_deg / 2.0).min(45.0));
+    let (sin_b, cos_b) = label_bearing.to_radians().sin_cos();
+    for i in 1..=opts.rings {
+        let r = radius * i as f64 / opts.rings as f64;
+        let label = format_range(range_max * i as f64 / opts.rings as f64);
+        let w = font::text_width(&label, scale) as f64;
+        let x = c + r * sin_b + 2.0 * scale as f64;
+        let y = c - r * cos_b - char_h - 2.0 * scale as f64;
+        let (x, y) = inside(x, y, w, char_h, scale, size);
+        draw::text_with_halo(img, x, y, &label, scale, label_color, halo);
+    }
+
+    // Spoke labels near the edge
+    if let Some(spoke_deg) = spoke_deg {
+        let n = (360.0 / spoke_deg).floor() as u32;
+        let r = radius - char_h * 1.5 - marker;
+        for i in 0..n {
+            let deg = i as f64 * spoke_deg;
+            if deg == 0.0 && opts.north_marker {
+                continue;
+            }
+            let theta = deg.to_radians();
+            let label = format!("{}\u{B0}", format_range(deg));
+            let w = font::text_width(&label, scale) as f64;
+            let x = c + r * theta.sin() - w / 2.0;
+            let y = c - r * theta.cos() - char_h / 2.0;
+            let (x, y) = inside(x, y, w, char_h, scale, size);
+            draw::text_with_halo(img, x, y, &label, scale, label_color, halo);
+        }
+    }
+}
+
+/// Draw the north marker of `opts` at `bearing_deg` clockwise from the top,
+/// e.g. minus the heading of a head-up image.
+pub fn draw_north_marker(img: &mut RgbaImage, bearing_deg: f64, opts: &OverlayOptions) {
+    let size = img.width().min(img.height());
+    let c = size as f64 / 2.0;
+    let radius = c;
+    let alpha = opts.alpha();
+    let [r, g, b] = opts.line_color;
+    let line_color = Rgba([r, g, b, alpha]);
+    let scale = (size / 600).max(1);
+    let marker = radius * 0.04;
+
+    // Point at `along` from the center towards the bearing, moved `across` clockwise
+    let (sin_b, cos_b) = bearing_deg.to_radians().sin_cos();
+    let at = |along: f64, across: f64| (c + along * sin_b + across * cos_b, c - along * cos_b + across * sin_b);
+    let base = radius - marker * 1.5;
+    draw::triangle(img, [at(radius, 0.0), at(base, -marker * 0.6), at(base, marker * 0.6)], line_color);
+
+    if opts.labels {
+        let [r, g, b] = opts.label_color;
+        let label_color = Rgba([r, g, b, alpha]);
+        let halo = Rgba([0, 0, 0, alpha / 2]);
+        let w = font::text_width("N", scale) as f64;
+        let h = font::text_height(scale) as f64;
+        let (x, y) = at(base - 2.0 * scale as f64 - h / 2.0, 0.0);
+        draw::text_with_halo(img, (x - w / 2.0) as i64, (y - h / 2.0) as i64, "N", scale, label_color, halo);
+    }
+}
+
+/// The overlay alone on a transparent `size` x `size` canvas.
+pub fn render_overlay(size: u32, range_max: f64, opts: &OverlayOptions) -> RgbaImage {
+    let mut img = RgbaImage::new(size, size);
+    draw_overlay(&mut img, range_max, opts);
+    img
+}
+
+/// Thread-safe cache of overlays for one image size, keyed by range.
+#[derive(Debug)]
+pub struct OverlayCache {
+    size: u32,
+    opts: OverlayOptions,
+    images: Mutex<HashMap<u64, Arc<RgbaImage>>>,
+}
+
+impl OverlayCache {
+    pub fn new(size: u32, opts: OverlayOptions) -> Self {
+        OverlayCache {
+            size,
+            opts,
+            images: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn options(&self) -> &OverlayOptions {
+        &self.opts
+    }
+
+    /// Overlay for `range_max`, and whether this call created it.
+    pub fn get(&self, range_max: f64) -> (Arc<RgbaImage>, bool) {
+        let mut images = self.images.lock().unwrap();
+        if let Some(img) = images.get(&range_max.to_bits()) {
+            return (img.clone(), false);
+        }
+        let img = Arc::new(render_overlay(self.size, range_max, &self.opts));
+        images.insert(range_max.to_bits(), img.clone());
+        (img, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    fn opts() -> OverlayOptions {
+        OverlayOptions {
+            rings: 2,
+            spoke_deg: 90.0,
+            north_marker: false,
+            labels: false,
+            opacity: 1.0,
+            line_width: 1.0,
+            ..OverlayOptions::default()
+        }
+    }
+
+    #[test]
+    fn range_labels_drop_trailing_zeros() {
+        assert_eq!(format_range(3.0), "3");
+        assert_eq!(format_range(1.5), "1.5");
+        assert_eq!(format_range(0.75), "0.75");
+        assert_eq!(format_range(2.0 / 3.0), "0.67");
+        assert_eq!(format_range(10.0), "10");
+    }
+
+    #[test]
+    fn spoke_spacing_and_opacity_are_clamped() {
+        let spacing = |spoke_deg: f64| OverlayOptions { spoke_deg, ..opts() }.spoke_spacing();
+        assert_eq!(spacing(30.0), Some(30.0));
+        assert_eq!(spacing(1e-12), Some(1.0));
+        assert_eq!(spacing(400.0), Some(180.0));
+        assert_eq!(spacing(0.0), None);
+        assert_eq!(spacing(-5.0), None);
+        assert_eq!(spacing(f64::NAN), None);
+        let alpha = |opacity: f32| OverlayOptions { opacity, ..opts() }.alpha();
+        assert_eq!((alpha(0.5), alpha(2.0), alpha(-1.0), alpha(f32::NAN)), (128, 255, 0, 0));
+    }
+
+    #[test]
+    fn rings_and_spokes_land_on_their_pixels() {
+        let img = render_overlay(100, 4.0, &opts());
+        let alpha = |x: u32, y: u32| img.get_pixel(x, y)[3];
+        // Rings at radius 25 and 50 (drawn just inside the edge)
+        assert!(alpha(74, 50) >= 127 && alpha(75, 50) >= 127 && alpha(50, 24) >= 127);
+        assert!(alpha(99, 50) > 0 && alpha(50, 0) > 0);
+        // Spokes towards 0, 90, 180 and 270°, none at 45°
+        assert!(alpha(49, 10) > 0 && alpha(90, 49) > 0 && alpha(49, 90) > 0 && alpha(10, 49) > 0);
+        assert_eq!(alpha(64, 35), 0);
+        // Nothing between the rings off the spokes
+        assert_eq!(alpha(60, 40), 0);
+    }
+
+    #[test]
+    fn labels_stay_inside_small_images() {
+        for size in [40, 64, 100] {
+            let opts = OverlayOptions {
+                rings: 4,
+                spoke_deg: 45.0,
+                labels: true,
+                label_color: [255, 0, 0],
+                line_color: [0, 0, 255],
+                ..opts()
+            };
+            let img = render_overlay(size, 12.5, &opts);
+            assert!(img.pixels().any(|&p| p == RED), "no labels at {size} px");
+            // Glyph pixels on the border would have their halo cut off
+            for (x, y, &p) in img.enumerate_pixels() {
+                let border = x == 0 || y == 0 || x == size - 1 || y == size - 1;
+                assert!(!(border && p == RED), "label pixel at ({x}, {y}) of {size} px");
+            }
+        }
+    }
+}
@@ -1,32 +1,126 @@
-//! CSV to PPI processing logic
+//! Batch CSV to PPI processing
 //! 
 //! Adapted from the original CSV_to_PPI_rust CLI tool
 
-use std::f64::consts::PI;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
-use colorous::{Color, MAGMA, TURBO, VIRIDIS};
-use image::{ImageBuffer, Rgba};
+use image::RgbaImage;
 use rayon::prelude::*;
 
+use crate::animation::{self, AnimationSettings};
+use crate::colormap::CMap;
+use crate::geo::GeoReference;
+use crate::kml::{self, GroundOverlay};
+use crate::grid::{regularize, Aggregation, GapFill, GridOptions};
+use crate::heading::{HeadingSettings, Headings, Orientation};
 use crate::queue::{self, FolderInfo};
+use crate::legend::{self, LegendOptions};
+use crate::lut::LutCache;
+use crate::manifest::{self, Manifest, ManifestEntry, ManifestFrame, ResumeMode};
+use crate::overlay::{self, OverlayCache, OverlayMode, OverlayOptions};
+use crate::render::{render_with_lut, AlphaRamp, Interpolation, Normalization, RenderOptions, Shader, Transfer};
+use crate::sweep::{read_csv_checked, read_csv_with, read_info, read_rotations, CsvFormat, RowProblem, Sweep};
+use crate::validation::{self, FileReport, ValidationMode};
+use crate::timestamp::Timestamp;
+use crate::watch::{FileWatcher, WatchSettings};
 
-#[derive(Clone)]
+/// Settings for a batch run, as entered in the GUI or on the command line.
+#[derive(Debug, Clone)]
 pub struct ProcessingSettings {
+    /// Pulses per revolution for resampling
     pub pulses: usize,
+    /// Max gap (degrees) to interpolate
     pub gap_deg: f64,
+    /// Filling of gaps up to `gap_deg`
+    pub gap_fill: GapFill,
+    /// Mounting correction (degrees) added to every pulse azimuth
+    pub azimuth_offset_deg: f64,
+    /// Combining of pulses that fall into the same grid step
+    pub aggregation: Aggregation,
+    /// Vessel heading and image orientation; without it sweeps are drawn
+    /// as recorded
+    pub heading: Option<HeadingSettings>,
+    /// Column layout of the CSV files
+    pub csv_format: CsvFormat,
+    /// Render one image per antenna rotation of files that span several
+    pub split_rotations: bool,
+    /// Checking of rendered files for malformed rows
+    pub validation: ValidationMode,
+    /// Output image dimensions (square)
     pub size: u32,
-    pub colormap: String,
+    /// Sampling of the grid for each pixel
+    pub interpolation: Interpolation,
+    /// Subsamples per pixel side for anti-aliased edges, 1 for off
+    pub antialias: u32,
+    /// Colormap of the rendered images
+    pub colormap: CMap,
+    /// Intensity normalization
+    pub normalization: Normalization,
+    /// Curve from normalized intensity to colormap position
+    pub transfer: Transfer,
+    /// Echoes below this value are transparent
+    pub noise_floor: Option<f32>,
+    /// Echoes above this value get the top color
+    pub ceiling: Option<f32>,
+    /// Opacity by colormap position
+    pub alpha: AlphaRamp,
+    /// Also write an animation of each folder's frames
+    pub animation: Option<AnimationSettings>,
+    /// Range rings and bearing overlay
+    pub overlay: Option<OverlayOptions>,
+    /// Colorbar legend and metadata caption
+    pub legend: Option<LegendOptions>,
+    /// Radar position for `.pgw`/`.prj` sidecar files
+    pub georef: Option<GeoReference>,
+    /// Also write a KMZ of each folder's images (needs `georef`)
+    pub kmz: bool,
+    /// Skip files rendered by an earlier run
+    pub resume: ResumeMode,
+    /// Keep watching the folders for new files after the initial batch
+    pub watch: Option<WatchSettings>,
+    /// Send [`ProgressUpdate::Preview`] images of rendered files
+    pub preview: bool,
+    /// Thread count (0 = 90% of cores)
     pub jobs: usize,
 }
 
+impl ProcessingSettings {
+    /// Resampling of each sweep onto the pulse grid
+    pub fn grid_options(&self) -> GridOptions {
+        GridOptions {
+            pulses: self.pulses,
+            gap_thresh: self.gap_deg.to_radians(),
+            gap_fill: self.gap_fill,
+            azimuth_offset: self.azimuth_offset_deg.to_radians(),
+            aggregation: self.aggregation,
+        }
+    }
+
+    /// Rendering of each grid, unrotated
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            size: self.size,
+            cmap: self.colormap.clone(),
+            normalization: self.normalization,
+            transfer: self.transfer,
+            noise_floor: self.noise_floor,
+            ceiling: self.ceiling,
+            alpha: self.alpha,
+            rotation: 0.0,
+            interpolation: self.interpolation,
+            antialias: self.antialias,
+        }
+    }
+}
 
+/// Progress events sent by [`process_folders`].
 #[derive(Debug)]
 pub enum ProgressUpdate {
     FolderStarted { folder_index: usize, folder_name: String },
@@ -37,60 +131,44 @@ pub enum ProgressUpdate {
         current_file: String,
         files_per_second: f64,
     },
-    FolderCompleted { folder_index: usize },
+    /// The folder is done; `files_flagged` files had malformed rows or
+    /// recorder setting changes
+    FolderCompleted { folder_index: usize, files_skipped: usize, files_flagged: usize },
     FolderError { folder_index: usize, error: String },
+    /// The folder's initial batch is done and new files are being watched for
+    FolderWatching { folder_index: usize },
+    /// A new file in a watched folder was rendered, or failed with `error`
+    FileWatched { folder_index: usize, file_name: String, error: Option<String> },
+    /// Downscaled copy of a freshly rendered image, at most a few per second
+    Preview { folder_index: usize, file_name: String, image: RgbaImage },
     AllComplete,
     Cancelled,
 }
 
-#[derive(Clone, Copy)]
-enum CMap {
-    Viridis,
-    Turbo,
-    Magma,
-    Gray,
-}
-
-impl CMap {
-    fn from_str(s: &str) -> Result<Self> {
-        match s.to_ascii_lowercase().as_str() {
-            "viridis" => Ok(Self::Viridis),
-            "turbo" => Ok(Self::Turbo),
-            "magma" => Ok(Self::Magma),
-            "gray" | "grey" | "grayscale" => Ok(Self::Gray),
-            _ => Err(anyhow!("Unknown colormap: {s}")),
-        }
-    }
-
-    fn eval(&self, v: f64) -> (u8, u8, u8) {
-        let v = v.clamp(0.0, 1.0);
-        match self {
-            Self::Viridis => to_rgb(VIRIDIS.eval_continuous(v)),
-            Self::Turbo => to_rgb(TURBO.eval_continuous(v)),
-            Self::Magma => to_rgb(MAGMA.eval_continuous(v)),
-            Self::Gray => {
-                let g = (v * 255.0).round() as u8;
-                (g, g, g)
-            }
-        }
-    }
-}
-
-fn to_rgb(c: Color) -> (u8, u8, u8) {
-    (c.r, c.g, c.b)
-}
-
 /// Process all folders in the queue
+///
+/// Each folder is rendered into a sibling `<name>_img_<pulses>` directory.
+/// When animation is enabled, `<name>.gif` or `<name>.png` is written there
+/// from the frames in sorted CSV filename order, and likewise `<name>.kmz`
+/// when KMZ output is enabled.
+/// With a [`ResumeMode`] other than `Off`, files rendered by an earlier run
+/// are skipped but still included in animations and KMZ files.
+/// With a [`ValidationMode`] other than `Off`, a report of the malformed rows
+/// of all rendered files is written there too; files whose range setting,
+/// gain or scale changes are always listed in it.
+/// In watch mode, CSV files still being written are left out of the initial
+/// batch, and the folders are then polled for new complete files, which are
+/// rendered as they arrive until `stop_flag` is set. Animations and KMZ files
+/// cover the initial batch only.
+/// Progress is reported on `tx`, along with preview images when enabled;
+/// setting `stop_flag` cancels the run.
 pub fn process_folders(
     folders: Vec<FolderInfo>,
     settings: ProcessingSettings,
     tx: Sender<ProgressUpdate>,
     stop_flag: Arc<AtomicBool>,
 ) {
-    let cmap = match CMap::from_str(&settings.colormap) {
-        Ok(c) => c,
-        Err(_) => CMap::Viridis,
-    };
+    let render_opts = settings.render_options();
     
     let jobs = if settings.jobs == 0 {
         ((num_cpus::get() as f64) * 0.9).ceil().max(1.0) as usize
@@ -109,6 +187,7 @@ pub fn process_folders(
         }
     };
     
+    let mut watched = Vec::new();
     for (folder_idx, folder) in folders.iter().enumerate() {
         // Check stop flag
         if stop_flag.load(Ordering::Relaxed) {
@@ -121,11 +200,15 @@ pub fn process_folders(
             folder_name: folder.name.clone(),
         });
         
-        // Get CSV files
-        let csv_files = queue::get_csv_files(&folder.path);
+        // Get CSV files, leaving out any still being written when watching
+        let mut watcher = settings.watch.map(|w| FileWatcher::new(w.settle));
+        let csv_files = match &mut watcher {
+            Some(watcher) => watcher.ready(queue::get_csv_files(&folder.path)),
+            None => queue::get_csv_files(&folder.path),
+        };
         let files_total = csv_files.len();
         
-        if files_total == 0 {
+        if files_total == 0 && watcher.is_none() {
             let _ = tx.send(ProgressUpdate::FolderError {
                 folder_index: folder_idx,
                 error: "No CSV files found".to_string(),
@@ -150,30 +233,99 @@ pub fn process_folders(
             continue;
         }
 
+        let headings = match settings.heading.as_ref().map(|h| Headings::load(&h.source)).transpose() {
+            Ok(headings) => headings,
+            Err(e) => {
+                let _ = tx.send(ProgressUpdate::FolderError {
+                    folder_index: folder_idx,
+                    error: format!("Failed to load headings: {:#}", e),
+                });
+                continue;
+            }
+        };
+        let orientation = settings.heading.as_ref().map_or(Orientation::NorthUp, |h| h.orientation);
+        // North turns with the heading in head-up images, so the shared
+        // overlays leave out the north marker and burned overlays get one
+        // drawn into each image
+        let head_up = headings.is_some() && orientation == Orientation::HeadUp;
+        let north_marker = head_up
+            && settings.overlay.as_ref().is_some_and(|o| o.north_marker && o.mode == OverlayMode::Burn);
+
+        // Anything that changes the images invalidates the manifest
+        let manifest = (settings.resume == ResumeMode::Manifest).then(|| {
+            let key = format!(
+                "{} {:?} {:?} {:?} {} {:?} {:?} {:?} {:?}",
+                env!("CARGO_PKG_VERSION"),
+                settings.grid_options(),
+                settings.heading,
+                settings.csv_format,
+                settings.split_rotations,
+                render_opts,
+                settings.overlay,
+                settings.legend,
+                settings.georef,
+            );
+            Mutex::new(Manifest::load(&output_dir, manifest::fnv1a(key.as_bytes())))
+        });
+
+        // Pixel geometry and overlays are shared by every file in the folder
+        let mut job = FolderJob {
+            output_dir: output_dir.clone(),
+            folder_name: folder_name.to_string(),
+            csv_format: settings.csv_format.clone(),
+            split_rotations: settings.split_rotations,
+            validation: settings.validation,
+            reports: Mutex::new(Vec::new()),
+            grid: settings.grid_options(),
+            render_opts: render_opts.clone(),
+            luts: LutCache::new(settings.size, settings.pulses),
+            overlays: settings.overlay.clone().map(|o| {
+                let north_marker = o.north_marker && !head_up;
+                OverlayCache::new(settings.size, OverlayOptions { north_marker, ..o })
+            }),
+            north_marker,
+            headings,
+            orientation,
+            legend: settings.legend.clone(),
+            georef: settings.georef,
+            resume: settings.resume,
+            manifest,
+            preview: settings.preview.then(|| PreviewSender::new(folder_idx, tx.clone())),
+        };
+
+        // Per-folder normalization needs the folder maximum, which takes
+        // reading every file, so a resumed folder with nothing left to render
+        // goes without it until a new file is watched. A folder that is still
+        // empty when watching falls back to per-image scaling.
+        if settings.normalization == Normalization::FolderMax
+            && (csv_files.is_empty() || pool.install(|| csv_files.par_iter().any(|path| !is_up_to_date(path, &job))))
+        {
+            pool.install(|| job.resolve_folder_max(&csv_files, &stop_flag));
+        }
         
         // Process files
         let files_done = AtomicUsize::new(0);
+        let files_skipped = AtomicUsize::new(0);
         let start_time = Instant::now();
         let last_update = Mutex::new(Instant::now());
+        let last_save = Mutex::new(Instant::now());
         let tx_clone = tx.clone();
         let stop_flag_clone = stop_flag.clone();
         
-        let results: Vec<Result<()>> = pool.install(|| {
-            csv_files.par_iter().map(|csv_path| -> Result<()> {
+        let results: Vec<Result<Option<Vec<Frame>>>> = pool.install(|| {
+            csv_files.par_iter().map(|csv_path| -> Result<Option<Vec<Frame>>> {
                 // Check stop flag periodically
                 if stop_flag_clone.load(Ordering::Relaxed) {
-                    return Ok(());
+                    return Ok(None);
                 }
                 
                 // Process single file
-                let result = process_single_csv(
-                    csv_path,
-                    &output_dir,
-                    settings.pulses,
-                    settings.gap_deg.to_radians(),
-                    settings.size,
-                    cmap,
-                );
+                let result = process_or_skip(csv_path, &job).map(|(frames, skipped)| {
+                    if skipped {
+                        files_skipped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    frames
+                });
                 
                 // Update progress
                 let done = files_done.fetch_add(1, Ordering::Relaxed) + 1;
@@ -200,291 +352,745 @@ pub fn process_folders(
                         files_per_second,
                     });
                 }
+                drop(last);
+
+                // Persist the manifest now and then so a crash loses little work
+                if job.manifest.is_some()
+                    && let Ok(mut last) = last_save.try_lock()
+                    && last.elapsed().as_secs() >= 10
+                {
+                    *last = Instant::now();
+                    let _ = job.save_manifest();
+                }
                 
-                result
+                result.map(Some)
             }).collect()
         });
         
         // Check for errors
         let errors: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
-        if !errors.is_empty() {
+        let mut folder_error = if errors.is_empty() {
+            None
+        } else {
+            Some(format!("{} files failed to process, first: {:#}", errors.len(), errors[0]))
+        };
+
+        // Save the manifest even when cancelled, so the next run resumes here
+        if let Err(e) = job.save_manifest() {
+            folder_error.get_or_insert_with(|| format!("Failed to write manifest: {:#}", e));
+        }
+        if let Err(e) = job.save_report() {
+            folder_error.get_or_insert_with(|| format!("Failed to write validation report: {:#}", e));
+        }
+
+        // Stitch rendered frames into an animation
+        if let Some(animation) = settings.animation
+            && !stop_flag.load(Ordering::Relaxed)
+        {
+            let (stems, frames): (Vec<&str>, Vec<PathBuf>) = rendered_frames(&csv_files, &results)
+                .map(|(stem, frame)| (stem, frame.path.clone()))
+                .unzip();
+            let delays = animation::frame_delays(&stems, animation.delay);
+            let output_path = output_dir.join(format!("{}.{}", folder_name, animation.format.extension()));
+            if let Err(e) = animation::write_animation(&frames, &delays, animation.format, &output_path, &stop_flag)
+                && !stop_flag.load(Ordering::Relaxed)
+            {
+                folder_error.get_or_insert_with(|| format!("Animation failed: {:#}", e));
+            }
+        }
+
+        // Bundle the georeferenced images for Google Earth
+        if settings.kmz
+            && let Some(georef) = settings.georef
+            && !stop_flag.load(Ordering::Relaxed)
+        {
+            let overlays: Vec<GroundOverlay> = rendered_frames(&csv_files, &results)
+                .map(|(stem, frame)| GroundOverlay {
+                    image: frame.path.clone(),
+                    name: match frame.rotation {
+                        Some(rotation) => format!("{} rotation {}", stem, rotation),
+                        None => stem.to_string(),
+                    },
+                    time: Timestamp::parse_filename(stem),
+                    bounds: georef.turned(frame.top_deg).lat_lon_box(frame.width, frame.height, frame.range_max),
+                })
+                .collect();
+            let output_path = output_dir.join(format!("{}.kmz", folder_name));
+            if let Err(e) = kml::write_kmz(folder_name, &overlays, &output_path) {
+                folder_error.get_or_insert_with(|| format!("KMZ failed: {:#}", e));
+            }
+        }
+
+        if let Some(error) = folder_error {
             let _ = tx.send(ProgressUpdate::FolderError {
                 folder_index: folder_idx,
-                error: format!("{} files failed to process", errors.len()),
+                error,
             });
+        } else if watcher.is_some() {
+            let _ = tx.send(ProgressUpdate::FolderWatching { folder_index: folder_idx });
         } else {
-            let _ = tx.send(ProgressUpdate::FolderCompleted { folder_index: folder_idx });
+            let _ = tx.send(ProgressUpdate::FolderCompleted {
+                folder_index: folder_idx,
+                files_skipped: files_skipped.load(Ordering::Relaxed),
+                files_flagged: validation::files_with_issues(&job.reports.lock().unwrap()),
+            });
+        }
+
+        if let Some(watcher) = watcher {
+            watched.push(WatchedFolder {
+                folder_index: folder_idx,
+                path: folder.path.clone(),
+                job,
+                watcher,
+            });
         }
     }
+
+    if let Some(watch) = settings.watch {
+        watch_folders(&mut watched, watch.poll_interval, &pool, &tx, &stop_flag);
+    }
     
     let _ = tx.send(ProgressUpdate::AllComplete);
 }
 
-/// Process a single CSV file
-fn process_single_csv(
-    csv_path: &PathBuf,
-    output_dir: &PathBuf,
-    pulses: usize,
-    gap_thresh: f64,
-    size: u32,
-    cmap: CMap,
-) -> Result<()> {
-    let (angles, bins, range_setting, gain, ts_str) = read_csv(csv_path)?;
-    
-    let output_name = format!("{}_{}_{}.png", ts_str, gain, range_setting);
-    let output_path = output_dir.join(output_name);
-    
-    let (_theta_edges, bins_resampled) = regularize(&angles, &bins, pulses, gap_thresh);
-    let png = render_png(&bins_resampled, range_setting, size, cmap)?;
-    
-    png.save(&output_path)
-        .with_context(|| format!("saving {}", output_path.display()))?;
-    
-    Ok(())
+/// Frames rendered or skipped in a batch, in file order, with the stem of
+/// the CSV file each came from
+fn rendered_frames<'a>(
+    csv_files: &'a [PathBuf],
+    results: &'a [Result<Option<Vec<Frame>>>],
+) -> impl Iterator<Item = (&'a str, &'a Frame)> {
+    csv_files.iter().zip(results).flat_map(|(csv_path, result)| {
+        let frames = result.as_ref().ok().and_then(Option::as_ref);
+        let stem = csv_path.file_stem().and_then(|s| s.to_str());
+        stem.zip(frames)
+            .into_iter()
+            .flat_map(|(stem, frames)| frames.iter().map(move |frame| (stem, frame)))
+    })
 }
 
-/// Read CSV into angle radians and bin matrix.
-fn read_csv(path: &PathBuf) -> Result<(Vec<f64>, Vec<Vec<f32>>, i32, i32, String)> {
-    let text = fs::read_to_string(path)
-        .with_context(|| format!("reading {}", path.display()))?;
-    let mut lines = text.lines();
-    let _header = lines
-        .next()
-        .ok_or_else(|| anyhow!("empty CSV: {}", path.display()))?;
-
-    let mut raw_angles = Vec::new();
-    let mut raw_bins: Vec<Vec<f32>> = Vec::new();
-    let mut range_setting = 0i32;
-    let mut gain_code = 0i32;
-
-    for line in lines {
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() < 6 {
-            continue; // Skip malformed lines
-        }
-        let angle_ticks: f64 = parts[4].parse().unwrap_or(0.0);
-        let row_bins: Vec<f32> = parts[5..]
-            .iter()
-            .map(|s| s.parse::<f32>().unwrap_or(0.0))
-            .collect();
-        raw_angles.push(angle_ticks * (2.0 * PI / 8192.0));
-        raw_bins.push(row_bins);
-        if range_setting == 0 {
-            range_setting = parts[2].parse().unwrap_or(0);
-        }
-        if gain_code == 0 {
-            gain_code = parts[3].parse().unwrap_or(0);
+/// Maximum echo value over all readable CSV files in a folder
+fn folder_max(csv_files: &[PathBuf], csv_format: &CsvFormat, stop_flag: &AtomicBool) -> f32 {
+    csv_files
+        .par_iter()
+        .filter(|_| !stop_flag.load(Ordering::Relaxed))
+        .filter_map(|path| read_csv_with(path, csv_format).ok())
+        .map(|sweep| {
+            sweep.bins
+                .iter()
+                .flatten()
+                .copied()
+                .filter(|v| v.is_finite())
+                .fold(0.0f32, f32::max)
+        })
+        .reduce(|| 0.0, f32::max)
+}
+
+/// Per-folder state shared by all workers
+struct FolderJob {
+    output_dir: PathBuf,
+    folder_name: String,
+    csv_format: CsvFormat,
+    split_rotations: bool,
+    validation: ValidationMode,
+    reports: Mutex<Vec<FileReport>>,
+    grid: GridOptions,
+    render_opts: RenderOptions,
+    luts: LutCache,
+    overlays: Option<OverlayCache>,
+    /// Draw the north marker into each image, turned by its heading
+    north_marker: bool,
+    headings: Option<Headings>,
+    orientation: Orientation,
+    legend: Option<LegendOptions>,
+    georef: Option<GeoReference>,
+    resume: ResumeMode,
+    manifest: Option<Mutex<Manifest>>,
+    preview: Option<PreviewSender>,
+}
+
+impl FolderJob {
+    /// Resolve per-folder normalization to the maximum of `csv_files`, or
+    /// to per-image scaling when they hold no echoes
+    fn resolve_folder_max(&mut self, csv_files: &[PathBuf], stop_flag: &AtomicBool) {
+        let max_val = folder_max(csv_files, &self.csv_format, stop_flag);
+        self.render_opts.normalization = if max_val > 0.0 {
+            Normalization::Fixed { min: 0.0, max: max_val }
+        } else {
+            Normalization::ImageMax
+        };
+        if let Some(manifest) = &mut self.manifest {
+            manifest.get_mut().unwrap().set_folder_max(max_val);
         }
     }
-    
-    if raw_angles.is_empty() {
-        return Err(anyhow!("no data rows in {}", path.display()));
-    }
 
-    // Merge duplicate angles by averaging
-    use std::collections::HashMap;
-    let mut angle_map: HashMap<u64, (Vec<Vec<f32>>, f64)> = HashMap::new();
-    
-    for (angle, bin_row) in raw_angles.iter().zip(raw_bins.iter()) {
-        let angle_key = (angle * 100000.0).round() as u64;
-        angle_map.entry(angle_key)
-            .or_insert_with(|| (Vec::new(), *angle))
-            .0.push(bin_row.clone());
+    /// Write the manifest, if there is one
+    fn save_manifest(&self) -> Result<()> {
+        match &self.manifest {
+            Some(manifest) => {
+                let json = manifest.lock().unwrap().to_json()?;
+                manifest::save(&self.output_dir, &json)
+            }
+            None => Ok(()),
+        }
     }
 
-    let mut angles = Vec::new();
-    let mut bins = Vec::new();
-
-    for (_key, (bin_rows, angle)) in angle_map.into_iter() {
-        angles.push(angle);
-        if bin_rows.len() == 1 {
-            bins.push(bin_rows[0].clone());
-        } else {
-            let n_bins = bin_rows[0].len();
-            let mut avg_bins = vec![0.0f32; n_bins];
-            for bin_row in &bin_rows {
-                for (i, &val) in bin_row.iter().enumerate() {
-                    if i < avg_bins.len() {
-                        avg_bins[i] += val;
-                    }
-                }
-            }
-            for val in &mut avg_bins {
-                *val /= bin_rows.len() as f32;
-            }
-            bins.push(avg_bins);
+    /// Write the validation report, if validation is enabled or a file
+    /// changed its recorder settings
+    fn save_report(&self) -> Result<()> {
+        let reports = self.reports.lock().unwrap();
+        if self.validation == ValidationMode::Off && validation::files_with_issues(&reports) == 0 {
+            return Ok(());
         }
+        validation::write_report(&self.output_dir, &self.folder_name, &reports)
     }
+}
 
-    let ts_str = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
+/// Largest side of preview images
+const PREVIEW_SIZE: u32 = 512;
+/// Minimum time between preview images
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(500);
 
-    Ok((angles, bins, range_setting, gain_code, ts_str))
+/// Sends a downscaled copy of rendered images now and then
+struct PreviewSender {
+    folder_index: usize,
+    tx: Sender<ProgressUpdate>,
+    last_sent: Mutex<Option<Instant>>,
 }
 
-/// Regularize pulses onto fixed grid
-fn regularize(
-    angles: &[f64],
-    bins: &[Vec<f32>],
-    pulses: usize,
-    gap_thresh: f64,
-) -> (Vec<f64>, Vec<Vec<f32>>) {
-    if bins.is_empty() {
-        return (Vec::new(), Vec::new());
+impl PreviewSender {
+    fn new(folder_index: usize, tx: Sender<ProgressUpdate>) -> Self {
+        PreviewSender {
+            folder_index,
+            tx,
+            last_sent: Mutex::new(None),
+        }
     }
-    
-    let n_bins = bins[0].len();
-    let mut idx: Vec<usize> = (0..angles.len()).collect();
-    idx.sort_by(|&a, &b| angles[a].partial_cmp(&angles[b]).unwrap());
 
-    let mut bins_resampled = vec![vec![f32::NAN; n_bins]; pulses];
+    /// Send `image` unless another preview went out less than
+    /// [`PREVIEW_INTERVAL`] ago
+    fn offer(&self, file_name: &str, image: &RgbaImage) {
+        {
+            let mut last_sent = self.last_sent.lock().unwrap();
+            if last_sent.is_some_and(|t| t.elapsed() < PREVIEW_INTERVAL) {
+                return;
+            }
+            *last_sent = Some(Instant::now());
+        }
 
-    for &i in &idx {
-        let theta = angles[i];
-        let pulse = ((theta / (2.0 * PI)) * pulses as f64).floor() as usize % pulses;
-        bins_resampled[pulse] = bins[i].clone();
+        let scale = PREVIEW_SIZE as f64 / image.width().max(image.height()) as f64;
+        let image = if scale < 1.0 {
+            let width = ((image.width() as f64 * scale).round() as u32).max(1);
+            let height = ((image.height() as f64 * scale).round() as u32).max(1);
+            image::imageops::thumbnail(image, width, height)
+        } else {
+            image.clone()
+        };
+        let _ = self.tx.send(ProgressUpdate::Preview {
+            folder_index: self.folder_index,
+            file_name: file_name.to_string(),
+            image,
+        });
     }
+}
 
-    let step_rad = 2.0 * PI / pulses as f64;
-    let mut has_data: Vec<bool> = bins_resampled
-        .iter()
-        .map(|row| row.iter().any(|v| v.is_finite()))
-        .collect();
+/// A folder being watched for new files
+struct WatchedFolder {
+    folder_index: usize,
+    path: PathBuf,
+    job: FolderJob,
+    watcher: FileWatcher,
+}
 
-    let mut i = 0;
-    while i < pulses {
-        if has_data[i] {
-            i += 1;
-            continue;
-        }
-        let run_start = i;
-        while i < pulses && !has_data[i] {
-            i += 1;
-        }
-        let run_end = i - 1;
+/// Render new files in the watched folders until `stop_flag` is set
+fn watch_folders(
+    folders: &mut [WatchedFolder],
+    poll_interval: Duration,
+    pool: &rayon::ThreadPool,
+    tx: &Sender<ProgressUpdate>,
+    stop_flag: &AtomicBool,
+) {
+    while !stop_flag.load(Ordering::Relaxed) {
+        let started = Instant::now();
+        for folder in folders.iter_mut() {
+            let new_files = folder.watcher.ready(queue::get_csv_files(&folder.path));
+            if new_files.is_empty() {
+                continue;
+            }
 
-        let mut prev = (run_start + pulses - 1) % pulses;
-        while !has_data[prev] && prev != run_start {
-            prev = (prev + pulses - 1) % pulses;
-        }
-        let mut next = run_end % pulses;
-        next = (next + 1) % pulses;
-        while !has_data[next] && next != run_start {
-            next = (next + 1) % pulses;
-        }
+            if folder.job.render_opts.normalization == Normalization::FolderMax {
+                let csv_files = queue::get_csv_files(&folder.path);
+                pool.install(|| folder.job.resolve_folder_max(&csv_files, stop_flag));
+            }
 
-        if !has_data[prev] || !has_data[next] {
-            continue;
-        }
+            let job = &folder.job;
+            let results: Vec<Result<(Vec<Frame>, bool)>> =
+                pool.install(|| new_files.par_iter().map(|csv_path| process_or_skip(csv_path, job)).collect());
+            for (csv_path, result) in new_files.iter().zip(results) {
+                let _ = tx.send(ProgressUpdate::FileWatched {
+                    folder_index: folder.folder_index,
+                    file_name: csv_path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string(),
+                    error: result.err().map(|e| format!("{:#}", e)),
+                });
+            }
 
-        let gap_steps = (next + pulses - prev) % pulses;
-        if gap_steps == 0 {
-            continue;
-        }
-        let missing_len = gap_steps - 1;
-        let gap_angle = gap_steps as f64 * step_rad;
-
-        if gap_angle <= gap_thresh {
-            let prev_row = bins_resampled[prev].clone();
-            let next_row = bins_resampled[next].clone();
-            for k in 1..=missing_len {
-                let t = k as f32 / (missing_len + 1) as f32;
-                let idx_fill = (prev + k) % pulses;
-                let mut filled = vec![0.0f32; n_bins];
-                for b in 0..n_bins {
-                    filled[b] = prev_row[b] * (1.0 - t) + next_row[b] * t;
-                }
-                bins_resampled[idx_fill] = filled;
-                has_data[idx_fill] = true;
+            if let Err(e) = job.save_manifest() {
+                let _ = tx.send(ProgressUpdate::FolderError {
+                    folder_index: folder.folder_index,
+                    error: format!("Failed to write manifest: {:#}", e),
+                });
             }
+            if let Err(e) = job.save_report() {
+                let _ = tx.send(ProgressUpdate::FolderError {
+                    folder_index: folder.folder_index,
+                    error: format!("Failed to write validation report: {:#}", e),
+                });
+            }
+        }
+
+        // Sleep in short steps so stopping stays responsive
+        while started.elapsed() < poll_interval && !stop_flag.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(50));
         }
     }
+}
 
-    let theta_edges: Vec<f64> = (0..=pulses)
-        .map(|p| p as f64 * 2.0 * PI / pulses as f64)
-        .collect();
-    (theta_edges, bins_resampled)
+/// A written image and the geometry needed to place it
+struct Frame {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    range_max: f64,
+    /// Direction of the image top, degrees clockwise from north
+    top_deg: f64,
+    /// Rotation number when the file was split
+    rotation: Option<usize>,
 }
 
-/// Render to RGBA PNG
-fn render_png(
-    bins: &[Vec<f32>],
-    range_setting: i32,
-    size: u32,
-    cmap: CMap,
-) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    if bins.is_empty() {
-        return Ok(ImageBuffer::new(size, size));
+/// Process a CSV file unless its output is up to date, returning the images
+/// and whether they were skipped
+fn process_or_skip(csv_path: &Path, job: &FolderJob) -> Result<(Vec<Frame>, bool)> {
+    if let Some(manifest) = &job.manifest {
+        let csv_name = csv_path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+        let input_hash = manifest::hash_file(csv_path)?;
+        if let Some(outputs) = manifest.lock().unwrap().lookup(&job.output_dir, &csv_name, input_hash) {
+            let numbered = outputs.len() > 1;
+            let frames = outputs
+                .into_iter()
+                .zip(1..)
+                .map(|((path, entry), number)| Frame {
+                    path,
+                    width: entry.width,
+                    height: entry.height,
+                    range_max: entry.range_max,
+                    top_deg: entry.top_deg,
+                    rotation: numbered.then_some(number),
+                })
+                .collect();
+            return Ok((frames, true));
+        }
+
+        let frames = process_single_csv(csv_path, job)?;
+        let entry = ManifestEntry {
+            input_hash,
+            frames: frames
+                .iter()
+                .map(|frame| ManifestFrame {
+                    output: frame.path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string(),
+                    width: frame.width,
+                    height: frame.height,
+                    range_max: frame.range_max,
+                    top_deg: frame.top_deg,
+                })
+                .collect(),
+        };
+        manifest.lock().unwrap().insert(csv_name, entry);
+        return Ok((frames, false));
     }
-    
-    let pulses = bins.len();
-    let n_bins = bins[0].len();
-
-    let mut max_val = 0.0f32;
-    for row in bins {
-        for &v in row {
-            if v.is_finite() && v > max_val {
-                max_val = v;
-            }
+
+    if job.resume == ResumeMode::Newer
+        && let Some(frames) = newer_output(csv_path, job)
+    {
+        return Ok((frames, true));
+    }
+    Ok((process_single_csv(csv_path, job)?, false))
+}
+
+/// Whether [`process_or_skip`] would skip `csv_path`
+fn is_up_to_date(csv_path: &Path, job: &FolderJob) -> bool {
+    match &job.manifest {
+        Some(manifest) => {
+            let csv_name = csv_path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            manifest::hash_file(csv_path)
+                .is_ok_and(|hash| manifest.lock().unwrap().lookup(&job.output_dir, csv_name, hash).is_some())
         }
+        None => job.resume == ResumeMode::Newer && newer_output(csv_path, job).is_some(),
     }
-    
-    let mut img = ImageBuffer::<Rgba<u8>, Vec<u8>>::new(size, size);
-    
-    if max_val <= 0.0 {
-        for (_, _, pixel) in img.enumerate_pixels_mut() {
-            *pixel = Rgba([0, 0, 0, 0]);
+}
+
+/// The existing outputs of `csv_path` if they are at least as new as the CSV.
+///
+/// Files that report no range setting are always rendered again, since
+/// their disk range depends on the full data, and so are head-up images
+/// with headings from the CSV. When rotations are split, the numbered
+/// outputs must match the count in the `.rotations` file written after the
+/// last of them, so a file whose run was cut short is rendered again.
+fn newer_output(csv_path: &Path, job: &FolderJob) -> Option<Vec<Frame>> {
+    let info = read_info(csv_path, &job.csv_format).ok()?;
+    if info.range_setting <= 0 {
+        return None;
+    }
+    // North-up images have north at the top whatever the heading
+    let top_deg = match (&job.headings, job.orientation) {
+        (Some(Headings::Column), Orientation::HeadUp) => return None,
+        (Some(headings), Orientation::HeadUp) => headings.heading(&info.timestamp, None).ok()?,
+        _ => 0.0,
+    };
+    let single = job.output_dir.join(info.output_name());
+    let input_time = fs::metadata(csv_path).ok()?.modified().ok()?;
+    let outputs: Vec<(PathBuf, Option<usize>)> = if job.split_rotations && !single.is_file() {
+        let count_path = job.output_dir.join(info.rotation_count_name());
+        if fs::metadata(&count_path).ok()?.modified().ok()? < input_time {
+            return None;
         }
-        return Ok(img);
+        let count: usize = fs::read_to_string(&count_path).ok()?.trim().parse().ok()?;
+        (1..=count)
+            .map(|rotation| (job.output_dir.join(info.rotation_output_name(rotation)), Some(rotation)))
+            .collect()
+    } else {
+        vec![(single, None)]
+    };
+    if outputs.is_empty() {
+        return None;
     }
 
-    let cx = size as f64 / 2.0;
-    let cy = size as f64 / 2.0;
-    let radius = cx.min(cy);
-    let range_max = if range_setting > 0 {
-        range_setting as f64
+    outputs
+        .into_iter()
+        .map(|(path, rotation)| {
+            let output_time = fs::metadata(&path).ok()?.modified().ok()?;
+            if output_time < input_time {
+                return None;
+            }
+            let (width, height) = image::image_dimensions(&path).ok()?;
+            Some(Frame {
+                path,
+                width,
+                height,
+                range_max: info.range_max(),
+                top_deg,
+                rotation,
+            })
+        })
+        .collect()
+}
+
+/// Process a single CSV file, returning the written images: one, or one per
+/// rotation when rotations are split
+fn process_single_csv(csv_path: &Path, job: &FolderJob) -> Result<Vec<Frame>> {
+    let (sweeps, mut issues) = if job.split_rotations {
+        read_rotations(csv_path, &job.csv_format)?
     } else {
-        n_bins as f64
+        read_csv_checked(csv_path, &job.csv_format).map(|(sweep, issues)| (vec![sweep], issues))?
     };
+    if job.validation == ValidationMode::Off {
+        // Range and gain changes are reported even without validation
+        issues.retain(|i| matches!(i.problem, RowProblem::SettingChanged { .. }));
+    }
+    let first = issues.first().cloned();
+    let count = issues.len();
+    let file = csv_path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+    job.reports.lock().unwrap().push(FileReport { file, issues });
+    if job.validation == ValidationMode::Strict
+        && let Some(first) = first
+    {
+        return Err(anyhow!("{} has {} flagged rows, first at {}", csv_path.display(), count, first));
+    }
+    let frames = sweeps.iter().map(|sweep| render_sweep(sweep, csv_path, job)).collect::<Result<Vec<_>>>()?;
 
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        let dx = x as f64 + 0.5 - cx;
-        let dy = cy - (y as f64 + 0.5);
-        let r_norm = (dx * dx + dy * dy).sqrt() / radius;
-        if r_norm > 1.0 {
-            *pixel = Rgba([0, 0, 0, 0]);
-            continue;
+    // Written last, so resuming can tell a complete set of rotations
+    if let Some(sweep) = sweeps.first().filter(|s| s.rotation.is_some()) {
+        let count_path = job.output_dir.join(sweep.rotation_count_name());
+        fs::write(&count_path, format!("{}\n", sweeps.len()))
+            .with_context(|| format!("writing {}", count_path.display()))?;
+    }
+    Ok(frames)
+}
+
+/// Render one sweep of `csv_path` with its overlays and sidecar files
+fn render_sweep(sweep: &Sweep, csv_path: &Path, job: &FolderJob) -> Result<Frame> {
+    let output_path = job.output_dir.join(sweep.output_name());
+
+    let heading = match &job.headings {
+        Some(headings) => Some(
+            headings
+                .heading(&sweep.timestamp, sweep.heading)
+                .with_context(|| format!("heading of {}", csv_path.display()))?,
+        ),
+        None => None,
+    };
+    let top_deg = heading.map_or(0.0, |h| job.orientation.top_deg(h));
+    let render_opts = RenderOptions {
+        rotation: heading.map_or(0.0, |h| job.orientation.rotation_deg(h).to_radians()),
+        ..job.render_opts.clone()
+    };
+
+    let grid = regularize(sweep, &job.grid);
+    let lut = job.luts.get(grid.n_bins())?;
+    let mut png = render_with_lut(&grid, &lut, &render_opts)?;
+
+    let range_max = sweep.range_max();
+    if let Some(overlays) = &job.overlays {
+        let (overlay, created) = overlays.get(range_max);
+        match overlays.options().mode {
+            OverlayMode::Burn => image::imageops::overlay(&mut png, overlay.as_ref(), 0, 0),
+            OverlayMode::Separate if created => {
+                // Named by the disk range the cache is keyed by, since with a
+                // scale column one range setting can span several
+                let overlay_path = job.output_dir.join(format!("overlay_{}.png", range_max));
+                overlay.save(&overlay_path)
+                    .with_context(|| format!("saving {}", overlay_path.display()))?;
+            }
+            OverlayMode::Separate => {}
         }
-        let mut theta = dx.atan2(dy);
-        if theta < 0.0 {
-            theta += 2.0 * PI;
+        if job.north_marker {
+            overlay::draw_north_marker(&mut png, -top_deg, overlays.options());
         }
+    }
 
-        let pulse_idx = ((theta / (2.0 * PI)) * pulses as f64).floor() as usize % pulses;
-        let r_val = r_norm * range_max;
-        let bin_idx = (r_val / range_max * n_bins as f64).floor() as usize;
-        if bin_idx >= n_bins {
-            *pixel = Rgba([0, 0, 0, 0]);
-            continue;
+    if let Some(legend) = &job.legend {
+        let shader = Shader::new(&render_opts, &grid);
+        png = legend::apply_legend(png, &shader, &legend::caption(sweep), legend);
+    }
+
+    png.save(&output_path)
+        .with_context(|| format!("saving {}", output_path.display()))?;
+
+    if let Some(preview) = &job.preview {
+        preview.offer(&sweep.output_name(), &png);
+    }
+
+    if let Some(georef) = &job.georef {
+        georef.turned(top_deg).write_sidecars(&output_path, job.render_opts.size, range_max)?;
+    }
+
+    Ok(Frame {
+        path: output_path,
+        width: png.width(),
+        height: png.height(),
+        range_max,
+        top_deg,
+        rotation: sweep.rotation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::sync::mpsc;
+    use std::time::SystemTime;
+
+    /// Empty `radar_plotter_processing_<pid>_<name>/cap` folder; the images
+    /// go to its sibling `cap_img_36`
+    fn capture_dir(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("radar_plotter_processing_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        let dir = root.join("cap");
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn output_dir(dir: &Path) -> PathBuf {
+        dir.with_file_name("cap_img_36")
+    }
+
+    /// Write a one-rotation capture `<stem>.csv` with echoes up to `peak`
+    fn write_capture(dir: &Path, stem: &str, peak: u32) -> PathBuf {
+        let path = dir.join(format!("{stem}.csv"));
+        let csv = format!("Status,Scale,Range,Gain,Angle,EchoValues\n1,496,3,60,0,1,{peak}\n1,496,3,60,4096,2,3\n");
+        fs::write(&path, csv).unwrap();
+        path
+    }
+
+    /// Write a capture `<stem>.csv` spanning three antenna rotations
+    fn write_rotations(dir: &Path, stem: &str) -> PathBuf {
+        let path = dir.join(format!("{stem}.csv"));
+        fs::write(
+            &path,
+            "Range,Gain,Angle,EchoValues\n\
+             3,60,4000,1\n3,60,8000,2\n\
+             3,60,100,3\n3,60,4096,4\n3,60,8100,5\n\
+             3,60,50,6\n",
+        )
+        .unwrap();
+        path
+    }
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    fn settings(resume: ResumeMode) -> ProcessingSettings {
+        ProcessingSettings {
+            pulses: 36,
+            gap_deg: 10.0,
+            gap_fill: GapFill::Linear,
+            azimuth_offset_deg: 0.0,
+            aggregation: Aggregation::Last,
+            heading: None,
+            csv_format: CsvFormat::default(),
+            split_rotations: false,
+            validation: ValidationMode::Off,
+            size: 32,
+            interpolation: Interpolation::Nearest,
+            antialias: 1,
+            colormap: CMap::Viridis,
+            normalization: Normalization::ImageMax,
+            transfer: Transfer::Linear,
+            noise_floor: None,
+            ceiling: None,
+            alpha: AlphaRamp::Opaque,
+            animation: None,
+            overlay: None,
+            legend: None,
+            georef: None,
+            kmz: false,
+            resume,
+            watch: None,
+            preview: false,
+            jobs: 2,
         }
+    }
 
-        let row = &bins[pulse_idx];
-        if bin_idx >= row.len() {
-            *pixel = Rgba([0, 0, 0, 0]);
-            continue;
+    /// Process `dir`, returning how many files were skipped
+    fn run(dir: &Path, settings: &ProcessingSettings) -> usize {
+        let folder = FolderInfo {
+            path: dir.to_path_buf(),
+            name: "cap".to_string(),
+            file_count: 0,
+            status: queue::FolderStatus::Pending,
+            progress: 0.0,
+            error_message: None,
+        };
+        let (tx, rx) = mpsc::channel();
+        process_folders(vec![folder], settings.clone(), tx, Arc::new(AtomicBool::new(false)));
+        let mut skipped = None;
+        for update in rx {
+            match update {
+                ProgressUpdate::FolderCompleted { files_skipped, .. } => skipped = Some(files_skipped),
+                ProgressUpdate::FolderError { error, .. } => panic!("{}", error),
+                _ => {}
+            }
         }
-        let v = row[bin_idx];
-        if !v.is_finite() || v == 0.0 {
-            *pixel = Rgba([0, 0, 0, 0]);
-            continue;
+        skipped.expect("folder not completed")
+    }
+
+    #[test]
+    fn newer_resume_renders_touched_and_missing_outputs() {
+        let dir = capture_dir("newer");
+        let settings = settings(ResumeMode::Newer);
+        let first = write_capture(&dir, "20240101120000", 5);
+        write_capture(&dir, "20240101120001", 5);
+        write_capture(&dir, "20240101120002", 5);
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(run(&dir, &settings), 3);
+
+        // A CSV touched after its image was written makes the image stale
+        let image = output_dir(&dir).join("20240101120000_60_3.png");
+        set_modified(&image, SystemTime::now() - Duration::from_secs(60));
+        set_modified(&first, SystemTime::now() - Duration::from_secs(30));
+        assert_eq!(run(&dir, &settings), 2);
+        assert_eq!(run(&dir, &settings), 3);
+
+        fs::remove_file(output_dir(&dir).join("20240101120001_60_3.png")).unwrap();
+        assert_eq!(run(&dir, &settings), 2);
+        assert!(output_dir(&dir).join("20240101120001_60_3.png").is_file());
+    }
+
+    #[test]
+    fn manifest_resume_follows_content_and_settings() {
+        let dir = capture_dir("manifest");
+        let mut settings = settings(ResumeMode::Manifest);
+        let first = write_capture(&dir, "20240101120000", 5);
+        write_capture(&dir, "20240101120001", 5);
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(run(&dir, &settings), 2);
+
+        // Touching alone keeps the manifest entry, new content does not
+        set_modified(&first, SystemTime::now() + Duration::from_secs(60));
+        assert_eq!(run(&dir, &settings), 2);
+        write_capture(&dir, "20240101120000", 9);
+        assert_eq!(run(&dir, &settings), 1);
+
+        fs::remove_file(output_dir(&dir).join("20240101120001_60_3.png")).unwrap();
+        assert_eq!(run(&dir, &settings), 1);
+        assert_eq!(run(&dir, &settings), 2);
+
+        // Other render settings change the settings hash
+        settings.colormap = CMap::Magma;
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(run(&dir, &settings), 2);
+    }
+
+    #[test]
+    fn folder_max_is_kept_in_the_manifest() {
+        let dir = capture_dir("folder_max");
+        let settings = ProcessingSettings {
+            normalization: Normalization::FolderMax,
+            ..settings(ResumeMode::Manifest)
+        };
+        write_capture(&dir, "20240101120000", 5);
+        write_capture(&dir, "20240101120001", 9);
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(run(&dir, &settings), 2);
+        let saved = fs::read_to_string(output_dir(&dir).join(manifest::MANIFEST_NAME)).unwrap();
+        assert_eq!(serde_json::from_str::<Manifest>(&saved).unwrap().folder_max, Some(9.0));
+
+        // The same maximum keeps the other image, a new one rescales both
+        write_capture(&dir, "20240101120000", 7);
+        assert_eq!(run(&dir, &settings), 1);
+        write_capture(&dir, "20240101120000", 12);
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(run(&dir, &settings), 2);
+    }
+
+    #[test]
+    fn newer_resume_renders_incomplete_rotation_sets() {
+        let dir = capture_dir("rotations");
+        let settings = ProcessingSettings { split_rotations: true, ..settings(ResumeMode::Newer) };
+        write_rotations(&dir, "20240101120000");
+        let output = output_dir(&dir);
+        let count_path = output.join("20240101120000_60_3.rotations");
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(fs::read_to_string(&count_path).unwrap(), "3\n");
+        assert_eq!(run(&dir, &settings), 1);
+
+        // Cut short after the images but before the count
+        fs::remove_file(&count_path).unwrap();
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(run(&dir, &settings), 1);
+
+        // A count the numbered images on disk don't match
+        for count in ["5\n", "two\n"] {
+            fs::write(&count_path, count).unwrap();
+            assert_eq!(run(&dir, &settings), 0);
+            assert_eq!(fs::read_to_string(&count_path).unwrap(), "3\n");
         }
-        let norm = (v / max_val).clamp(0.0, 1.0) as f64;
-        let (r, g, b) = cmap.eval(norm);
-        *pixel = Rgba([r, g, b, 255]);
+        fs::remove_file(output.join("20240101120000_60_3_r002.png")).unwrap();
+        assert_eq!(run(&dir, &settings), 0);
+        assert!(output.join("20240101120000_60_3_r002.png").is_file());
+    }
+
+    #[test]
+    fn manifest_resume_renders_missing_rotations() {
+        let dir = capture_dir("manifest_rotations");
+        let settings = ProcessingSettings { split_rotations: true, ..settings(ResumeMode::Manifest) };
+        write_rotations(&dir, "20240101120000");
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(run(&dir, &settings), 1);
+
+        fs::remove_file(output_dir(&dir).join("20240101120000_60_3_r003.png")).unwrap();
+        assert_eq!(run(&dir, &settings), 0);
+        assert_eq!(run(&dir, &settings), 1);
     }
-    
-    Ok(img)
 }
@@ -8,6 +8,8 @@ pub enum FolderStatus {
     Processing,
     Complete,
     Error,
+    /// Initial batch done, rendering new files as they appear
+    Watching,
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,781 @@
+//! Rendering of regularized polar grids to PPI images
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use crate::colormap::{CMap, ColorTable};
+use crate::grid::PolarGrid;
+use crate::lut::PolarLut;
+
+/// How echo values are scaled to `[0, 1]` before the colormap is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Normalization {
+    /// Divide by the maximum echo of each image
+    #[default]
+    ImageMax,
+    /// Map a fixed `[min, max]` echo range, e.g. the recorder's 0-255
+    Fixed { min: f32, max: f32 },
+    /// Divide by the maximum echo of the whole folder.
+    ///
+    /// [`process_folders`](crate::processing::process_folders) computes the
+    /// maximum in a first pass and renders with [`Normalization::Fixed`].
+    /// Rendering a single grid with this mode behaves like `ImageMax`.
+    FolderMax,
+    /// Clip at the given percentile (0-100) of the non-zero echoes of each image
+    Percentile(f32),
+}
+
+impl FromStr for Normalization {
+    type Err = Error;
+
+    /// Parse `image`, `folder`, `fixed[:MIN:MAX]` or `percentile[:P]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let mut parts = lower.split(':');
+        let mode = parts.next().unwrap_or("");
+        let args: Vec<f32> = parts
+            .map(|p| p.trim().parse::<f32>().map_err(|_| anyhow!("Invalid number in normalization: {s}")))
+            .collect::<Result<_>>()?;
+        match (mode, args.as_slice()) {
+            ("image", []) => Ok(Self::ImageMax),
+            ("folder", []) => Ok(Self::FolderMax),
+            ("fixed", []) => Ok(Self::Fixed { min: 0.0, max: 255.0 }),
+            ("fixed", [min, max]) if max > min => Ok(Self::Fixed { min: *min, max: *max }),
+            ("percentile", []) => Ok(Self::Percentile(99.0)),
+            ("percentile", [p]) if (0.0..=100.0).contains(p) => Ok(Self::Percentile(*p)),
+            _ => Err(anyhow!("Unknown normalization: {s}")),
+        }
+    }
+}
+
+impl Normalization {
+    /// Echo range `(min, max)` that maps to the ends of the colormap.
+    pub fn range(&self, grid: &PolarGrid) -> (f32, f32) {
+        match *self {
+            Self::ImageMax | Self::FolderMax => (0.0, grid_max(grid)),
+            Self::Fixed { min, max } => (min, max),
+            Self::Percentile(p) => (0.0, grid_percentile(grid, p)),
+        }
+    }
+}
+
+/// How each pixel samples the polar grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// The grid cell under the pixel, read from a shared [`PolarLut`]
+    #[default]
+    Nearest,
+    /// Blend of the four surrounding cell centers in angle and range, for
+    /// smooth output at large image sizes
+    Bilinear,
+}
+
+impl FromStr for Interpolation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Self::Nearest),
+            "bilinear" => Ok(Self::Bilinear),
+            _ => Err(anyhow!("Unknown interpolation: {s}")),
+        }
+    }
+}
+
+/// Curve applied to normalized intensities before the colormap.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Transfer {
+    /// Colors proportional to echo strength
+    #[default]
+    Linear,
+    /// Decibels below the top of the range; the given number of dB spans
+    /// the colormap, so weak echoes get visibly different colors
+    Decibel { range_db: f32 },
+    /// `t^gamma`; below 1 lifts weak echoes, above 1 suppresses them
+    Gamma(f32),
+}
+
+impl FromStr for Transfer {
+    type Err = Error;
+
+    /// Parse `linear`, `log`/`db[:RANGE]` (default 30 dB) or `gamma[:G]` (default 0.5).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let mut parts = lower.split(':');
+        let mode = parts.next().unwrap_or("");
+        let args: Vec<f32> = parts
+            .map(|p| p.trim().parse::<f32>().map_err(|_| anyhow!("Invalid number in transfer: {s}")))
+            .collect::<Result<_>>()?;
+        match (mode, args.as_slice()) {
+            ("linear", []) => Ok(Self::Linear),
+            ("log" | "db", []) => Ok(Self::Decibel { range_db: 30.0 }),
+            ("log" | "db", [range_db]) if *range_db > 0.0 => Ok(Self::Decibel { range_db: *range_db }),
+            ("gamma", []) => Ok(Self::Gamma(0.5)),
+            ("gamma", [gamma]) if *gamma > 0.0 => Ok(Self::Gamma(*gamma)),
+            _ => Err(anyhow!("Unknown transfer: {s}")),
+        }
+    }
+}
+
+impl Transfer {
+    /// Colormap position of a normalized intensity in `[0, 1]`.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Self::Linear => t,
+            Self::Decibel { range_db } => (1.0 + 10.0 * t.log10() / range_db).max(0.0),
+            Self::Gamma(gamma) => t.powf(gamma),
+        }
+    }
+
+    /// Normalized intensity at colormap position `p`, the inverse of [`apply`](Self::apply).
+    pub fn invert(&self, p: f32) -> f32 {
+        let p = p.clamp(0.0, 1.0);
+        match *self {
+            Self::Linear => p,
+            Self::Decibel { .. } if p == 0.0 => 0.0,
+            Self::Decibel { range_db } => 10f32.powf((p - 1.0) * range_db / 10.0),
+            Self::Gamma(gamma) => p.powf(1.0 / gamma),
+        }
+    }
+}
+
+/// Opacity of echoes by colormap position.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlphaRamp {
+    /// Every echo fully opaque (within the colormap's own alpha)
+    #[default]
+    Opaque,
+    /// Transparent up to colormap position `start`, opaque from `end`, and
+    /// `((p - start) / (end - start))^gamma` in between; a gamma above 1
+    /// keeps weak echoes fainter for longer
+    Ramp { start: f32, end: f32, gamma: f32 },
+}
+
+impl FromStr for AlphaRamp {
+    type Err = Error;
+
+    /// Parse `opaque` or `ramp[:START:END[:GAMMA]]` (default `ramp:0:1:1`),
+    /// with `START` and `END` fractions of the colormap.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let mut parts = lower.split(':');
+        let mode = parts.next().unwrap_or("");
+        let args: Vec<f32> = parts
+            .map(|p| p.trim().parse::<f32>().map_err(|_| anyhow!("Invalid number in alpha ramp: {s}")))
+            .collect::<Result<_>>()?;
+        let valid = |start: f32, end: f32, gamma: f32| (0.0..end).contains(&start) && end <= 1.0 && gamma > 0.0;
+        match (mode, args.as_slice()) {
+            ("opaque" | "off", []) => Ok(Self::Opaque),
+            ("ramp", []) => Ok(Self::Ramp { start: 0.0, end: 1.0, gamma: 1.0 }),
+            ("ramp", [start, end]) if valid(*start, *end, 1.0) => Ok(Self::Ramp { start: *start, end: *end, gamma: 1.0 }),
+            ("ramp", [start, end, gamma]) if valid(*start, *end, *gamma) => {
+                Ok(Self::Ramp { start: *start, end: *end, gamma: *gamma })
+            }
+            _ => Err(anyhow!("Unknown alpha ramp: {s}")),
+        }
+    }
+}
+
+impl AlphaRamp {
+    /// Opacity in `[0, 1]` at colormap position `p`.
+    pub fn apply(&self, p: f64) -> f64 {
+        match *self {
+            Self::Opaque => 1.0,
+            Self::Ramp { start, end, gamma } => {
+                ((p - start as f64) / (end - start) as f64).clamp(0.0, 1.0).powf(gamma as f64)
+            }
+        }
+    }
+}
+
+/// Options controlling how a [`PolarGrid`] is rendered.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Output image width and height in pixels
+    pub size: u32,
+    /// Colormap applied to normalized intensities
+    pub cmap: CMap,
+    /// Intensity normalization
+    pub normalization: Normalization,
+    /// Curve from normalized intensity to colormap position
+    pub transfer: Transfer,
+    /// Echoes below this value are transparent, and the colormap starts
+    /// here instead of at the normalization minimum
+    pub noise_floor: Option<f32>,
+    /// Echoes above this value get the top color, and the colormap ends
+    /// here instead of at the normalization maximum
+    pub ceiling: Option<f32>,
+    /// Opacity by colormap position, so weak echoes can let the map below
+    /// show through
+    pub alpha: AlphaRamp,
+    /// Clockwise rotation of the image in radians, e.g. the vessel heading
+    /// to turn bow-relative sweeps north-up
+    pub rotation: f64,
+    /// Sampling of the grid
+    pub interpolation: Interpolation,
+    /// Subsamples per pixel side at the disk edge and at boundaries of
+    /// missing data, giving those pixels partial alpha; 1 turns this off
+    pub antialias: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            size: 1735,
+            cmap: CMap::Viridis,
+            normalization: Normalization::ImageMax,
+            transfer: Transfer::Linear,
+            noise_floor: None,
+            ceiling: None,
+            alpha: AlphaRamp::Opaque,
+            rotation: 0.0,
+            interpolation: Interpolation::Nearest,
+            antialias: 1,
+        }
+    }
+}
+
+/// Echo values to colors for one grid, as set by [`RenderOptions`].
+#[derive(Debug, Clone)]
+pub struct Shader {
+    colors: ColorTable,
+    min: f32,
+    max: f32,
+    floor: Option<f32>,
+    transfer: Transfer,
+}
+
+impl Shader {
+    pub fn new(opts: &RenderOptions, grid: &PolarGrid) -> Self {
+        let (mut min, mut max) = opts.normalization.range(grid);
+        if let Some(floor) = opts.noise_floor {
+            min = min.max(floor);
+        }
+        if let Some(ceiling) = opts.ceiling {
+            max = max.min(ceiling);
+        }
+        let mut colors = ColorTable::new(&opts.cmap);
+        if opts.alpha != AlphaRamp::Opaque {
+            colors.fade(|p| opts.alpha.apply(p));
+        }
+        Shader {
+            colors,
+            min,
+            max,
+            floor: opts.noise_floor,
+            transfer: opts.transfer,
+        }
+    }
+
+    /// Echo values `(min, max)` at the ends of the colormap.
+    pub fn range(&self) -> (f32, f32) {
+        (self.min, self.max)
+    }
+
+    /// Curve between the ends of the range and the colormap.
+    pub fn transfer(&self) -> Transfer {
+        self.transfer
+    }
+
+    /// RGBA color at colormap position `p` in `[0, 1]`, as drawn in a colorbar.
+    pub fn at_position(&self, p: f32) -> [u8; 4] {
+        self.colors.get(p)
+    }
+
+    /// Whether no echo gets a color.
+    pub fn is_empty(&self) -> bool {
+        self.max <= self.min
+    }
+
+    /// RGBA color of echo `v`, `None` where the pixel stays transparent: no
+    /// data, zero, or below the noise floor.
+    #[inline]
+    pub fn color(&self, v: f32) -> Option<[u8; 4]> {
+        if !v.is_finite() || v == 0.0 || self.floor.is_some_and(|floor| v < floor) {
+            return None;
+        }
+        let t = (v - self.min) / (self.max - self.min);
+        Some(self.colors.get(self.transfer.apply(t)))
+    }
+}
+
+/// Maximum finite echo value in the grid, or 0 when there is none.
+pub fn grid_max(grid: &PolarGrid) -> f32 {
+    let mut max_val = 0.0f32;
+    for row in &grid.bins {
+        for &v in row {
+            if v.is_finite() && v > max_val {
+                max_val = v;
+            }
+        }
+    }
+    max_val
+}
+
+/// The `p`-th percentile (0-100) of the finite, non-zero echo values.
+fn grid_percentile(grid: &PolarGrid, p: f32) -> f32 {
+    let mut values: Vec<f32> = grid.bins
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|v| v.is_finite() && *v != 0.0)
+        .collect();
+    if values.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p.clamp(0.0, 100.0) / 100.0) * (values.len() - 1) as f32).round() as usize;
+    let (_, v, _) = values.select_nth_unstable_by(rank, |a, b| a.total_cmp(b));
+    *v
+}
+
+/// Value at a disk position given in radii east and north of the center,
+/// blended from the four nearest cell centers of a flattened grid that is
+/// turned clockwise by `turn` radians.
+fn sample_bilinear(flat: &[f32], pulses: usize, n_bins: usize, turn: f64, east: f64, north: f64) -> f32 {
+    let theta = (east.atan2(north) - turn).rem_euclid(2.0 * PI);
+    // Cell centers sit at whole coordinates; angles wrap, ranges clamp
+    let u = theta / (2.0 * PI) * pulses as f64 - 0.5;
+    let w = (east.hypot(north) * n_bins as f64 - 0.5).clamp(0.0, (n_bins - 1) as f64);
+    let (u0, w0) = (u.floor(), w.floor());
+    let (fu, fw) = ((u - u0) as f32, (w - w0) as f32);
+    let p0 = (u0 as isize).rem_euclid(pulses as isize) as usize;
+    let p1 = (p0 + 1) % pulses;
+    let b0 = w0 as usize;
+    let b1 = (b0 + 1).min(n_bins - 1);
+
+    let mut sum = 0.0;
+    let mut weight = 0.0;
+    for (p, b, wt) in [
+        (p0, b0, (1.0 - fu) * (1.0 - fw)),
+        (p0, b1, (1.0 - fu) * fw),
+        (p1, b0, fu * (1.0 - fw)),
+        (p1, b1, fu * fw),
+    ] {
+        let v = flat[p * n_bins + b];
+        if v.is_finite() {
+            sum += v * wt;
+            weight += wt;
+        }
+    }
+    if weight < 0.5 { f32::NAN } else { sum / weight }
+}
+
+/// Value at a disk position given in radii east and north of the center of
+/// a flattened grid turned clockwise by `turn` radians, `NaN` outside the
+/// disk.
+fn sample(
+    flat: &[f32],
+    pulses: usize,
+    n_bins: usize,
+    interpolation: Interpolation,
+    turn: f64,
+    east: f64,
+    north: f64,
+) -> f32 {
+    let r_norm = east.hypot(north);
+    if r_norm > 1.0 {
+        return f32::NAN;
+    }
+    match interpolation {
+        Interpolation::Nearest => {
+            let theta = (east.atan2(north) - turn).rem_euclid(2.0 * PI);
+            let pulse = ((theta / (2.0 * PI)) * pulses as f64).floor() as usize % pulses;
+            let bin = ((r_norm * n_bins as f64).floor() as usize).min(n_bins - 1);
+            flat[pulse * n_bins + bin]
+        }
+        Interpolation::Bilinear => sample_bilinear(flat, pulses, n_bins, turn, east, north),
+    }
+}
+
+/// Give pixels on the disk edge and on boundaries of missing data an alpha
+/// equal to the fraction of `samples` x `samples` subsamples that get a color.
+///
+/// `data` tells which pixels have an echo value, zero included; only where
+/// it changes are pixels supersampled. Edges between weak and strong echoes,
+/// or zeros and echoes, are left sharp, so flat areas below the noise floor
+/// don't cost any subsamples. `value` maps a position in radii east and
+/// north of the center to an echo, `shader` gives echoes their color. Files
+/// are rendered in parallel, so this pass over one image stays serial.
+fn smooth_edges(
+    img: &mut RgbaImage,
+    samples: u32,
+    data: &[bool],
+    value: impl Fn(f64, f64) -> f32,
+    shader: &Shader,
+) {
+    let size = img.width();
+    let center = size as f64 / 2.0;
+    let has_data = |x: i64, y: i64| {
+        (0..size as i64).contains(&x) && (0..size as i64).contains(&y) && data[(y * size as i64 + x) as usize]
+    };
+
+    for y in 0..size {
+        for x in 0..size {
+            let (xi, yi) = (x as i64, y as i64);
+            let here = has_data(xi, yi);
+            let r_px = (x as f64 + 0.5 - center).hypot(center - (y as f64 + 0.5));
+            let on_disk_edge = (r_px - center).abs() < 1.0;
+            let on_boundary = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .any(|&(ox, oy)| has_data(xi + ox, yi + oy) != here);
+            if !on_disk_edge && !on_boundary {
+                continue;
+            }
+
+            let mut covered = 0u32;
+            let mut sum = 0.0f32;
+            for sy in 0..samples {
+                for sx in 0..samples {
+                    let dx = x as f64 + (sx as f64 + 0.5) / samples as f64 - center;
+                    let dy = center - (y as f64 + (sy as f64 + 0.5) / samples as f64);
+                    let v = value(dx / center, dy / center);
+                    if shader.color(v).is_some() {
+                        covered += 1;
+                        sum += v;
+                    }
+                }
+            }
+            let pixel = img.get_pixel_mut(x, y);
+            if covered == 0 {
+                *pixel = Rgba([0, 0, 0, 0]);
+                continue;
+            }
+            // Pixels that had no color take the color of the covered part
+            let [r, g, b, a] = match shader.color(sum / covered as f32) {
+                Some(color) if pixel[3] == 0 => color,
+                _ => pixel.0,
+            };
+            let alpha = (a as f32 * covered as f32 / (samples * samples) as f32).round() as u8;
+            *pixel = Rgba([r, g, b, alpha]);
+        }
+    }
+}
+
+/// Render a polar grid to a square, transparent RGBA PPI image.
+///
+/// Azimuth 0 is at the top, turned clockwise by `opts.rotation`, and azimuth
+/// increases clockwise. The outermost range bin touches the image edge. Values are scaled according to
+/// `opts.normalization`, narrowed to `opts.noise_floor` and `opts.ceiling`,
+/// and mapped onto the colormap through `opts.transfer`. Zero and missing
+/// values and echoes below the noise floor are transparent, and
+/// `opts.alpha` fades the rest by colormap position. With
+/// [`Interpolation::Bilinear`] a pixel is transparent when less than half of
+/// its weight falls on cells with data. With `opts.antialias` above 1, pixels
+/// on the disk edge and on boundaries of missing data are supersampled and
+/// get partial alpha by coverage; all other pixels take the same fast path.
+///
+/// This builds a [`PolarLut`] for every call; use [`render_with_lut`] with a
+/// shared table when rendering many grids of the same shape.
+pub fn render_png(grid: &PolarGrid, opts: &RenderOptions) -> Result<RgbaImage> {
+    if grid.is_empty() || grid.n_bins() == 0 {
+        return Ok(ImageBuffer::new(opts.size, opts.size));
+    }
+    let lut = PolarLut::new(opts.size, grid.pulses(), grid.n_bins())?;
+    render_with_lut(grid, &lut, opts)
+}
+
+/// Render a polar grid using a precomputed lookup table.
+///
+/// The table must have been built for `opts.size` and the grid's shape.
+pub fn render_with_lut(grid: &PolarGrid, lut: &PolarLut, opts: &RenderOptions) -> Result<RgbaImage> {
+    let size = opts.size;
+    let pulses = grid.pulses();
+    let n_bins = grid.n_bins();
+    if pulses == 0 || n_bins == 0 {
+        return Ok(ImageBuffer::new(size, size));
+    }
+    if !lut.matches(size, pulses, n_bins) {
+        return Err(anyhow!(
+            "lookup table does not match {size}px image of {pulses}x{n_bins} grid"
+        ));
+    }
+
+    let shader = Shader::new(opts, grid);
+    let mut img = RgbaImage::new(size, size);
+    if shader.is_empty() {
+        return Ok(img);
+    }
+
+    // Flatten rows so each pixel is a single gather; short rows are padded
+    let mut flat = vec![f32::NAN; pulses * n_bins];
+    for (dst, row) in flat.chunks_exact_mut(n_bins).zip(&grid.bins) {
+        let n = row.len().min(n_bins);
+        dst[..n].copy_from_slice(&row[..n]);
+    }
+
+    let center = size as f64 / 2.0;
+    // Pixels with an echo value, for finding the edges to anti-alias
+    let mut data = vec![false; if opts.antialias > 1 { size as usize * size as usize } else { 0 }];
+    for (i, (pixel, idx)) in img.pixels_mut().zip(lut.indices(opts.rotation)).enumerate() {
+        let Some(idx) = idx else {
+            continue;
+        };
+        let v = match opts.interpolation {
+            Interpolation::Nearest => flat[idx],
+            Interpolation::Bilinear => {
+                let dx = (i as u32 % size) as f64 + 0.5 - center;
+                let dy = center - ((i as u32 / size) as f64 + 0.5);
+                sample_bilinear(&flat, pulses, n_bins, opts.rotation, dx / center, dy / center)
+            }
+        };
+        if let Some(color) = shader.color(v) {
+            *pixel = Rgba(color);
+        }
+        if let Some(has_data) = data.get_mut(i) {
+            *has_data = v.is_finite();
+        }
+    }
+
+    if opts.antialias > 1 {
+        smooth_edges(
+            &mut img,
+            opts.antialias,
+            &data,
+            |east, north| sample(&flat, pulses, n_bins, opts.interpolation, opts.rotation, east, north),
+            &shader,
+        );
+    }
+
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalization_parsing() {
+        assert_eq!("image".parse::<Normalization>().unwrap(), Normalization::ImageMax);
+        assert_eq!("Folder".parse::<Normalization>().unwrap(), Normalization::FolderMax);
+        assert_eq!("fixed".parse::<Normalization>().unwrap(), Normalization::Fixed { min: 0.0, max: 255.0 });
+        assert_eq!("fixed:10:50".parse::<Normalization>().unwrap(), Normalization::Fixed { min: 10.0, max: 50.0 });
+        assert_eq!("percentile:95".parse::<Normalization>().unwrap(), Normalization::Percentile(95.0));
+        for invalid in ["fixed:50:10", "fixed:10", "percentile:101", "percentile:x", "max"] {
+            assert!(invalid.parse::<Normalization>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn normalization_ranges() {
+        let grid = PolarGrid {
+            theta_edges: vec![0.0, PI, 2.0 * PI],
+            bins: vec![(0..=10).map(|v| v as f32).collect(), vec![f32::NAN, 100.0]],
+        };
+        assert_eq!(grid_max(&grid), 100.0);
+        assert_eq!(Normalization::ImageMax.range(&grid), (0.0, 100.0));
+        assert_eq!(Normalization::Fixed { min: 5.0, max: 50.0 }.range(&grid), (5.0, 50.0));
+        // Of the non-zero echoes 1-10 and 100, the median is 6
+        assert_eq!(Normalization::Percentile(50.0).range(&grid), (0.0, 6.0));
+        assert_eq!(Normalization::Percentile(100.0).range(&grid), (0.0, 100.0));
+    }
+
+    /// Grid of four 90° pulses with echoes only in the first, at 0-90°
+    fn quarter_grid() -> PolarGrid {
+        PolarGrid {
+            theta_edges: (0..=4).map(|p| p as f64 * PI / 2.0).collect(),
+            bins: vec![vec![1.0; 4], vec![0.0; 4], vec![0.0; 4], vec![0.0; 4]],
+        }
+    }
+
+    /// The pixel at `bearing_deg` halfway out on a 100 px image
+    fn pixel_at(img: &RgbaImage, bearing_deg: f64) -> [u8; 4] {
+        let (sin, cos) = bearing_deg.to_radians().sin_cos();
+        img.get_pixel((50.0 + 25.0 * sin) as u32, (50.0 - 25.0 * cos) as u32).0
+    }
+
+    #[test]
+    fn transfer_parsing() {
+        assert_eq!("linear".parse::<Transfer>().unwrap(), Transfer::Linear);
+        assert_eq!("log".parse::<Transfer>().unwrap(), Transfer::Decibel { range_db: 30.0 });
+        assert_eq!("dB:20".parse::<Transfer>().unwrap(), Transfer::Decibel { range_db: 20.0 });
+        assert_eq!("gamma".parse::<Transfer>().unwrap(), Transfer::Gamma(0.5));
+        assert_eq!("gamma:2".parse::<Transfer>().unwrap(), Transfer::Gamma(2.0));
+        for invalid in ["db:0", "gamma:-1", "gamma:1:2", "linear:1", "sqrt"] {
+            assert!(invalid.parse::<Transfer>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn transfer_curves() {
+        let db = Transfer::Decibel { range_db: 30.0 };
+        // 10 dB below the top is a third of the way down; 30 dB and below is the bottom
+        assert!((db.apply(0.1) - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(db.apply(0.001), 0.0);
+        assert_eq!(db.apply(0.0), 0.0);
+        assert_eq!(Transfer::Gamma(0.5).apply(0.25), 0.5);
+        assert_eq!(Transfer::Linear.apply(1.5), 1.0);
+        for transfer in [Transfer::Linear, db, Transfer::Gamma(0.5), Transfer::Gamma(2.0)] {
+            for t in [0.01, 0.2, 0.5, 1.0] {
+                let back = transfer.invert(transfer.apply(t));
+                assert!((back - t).abs() < 1e-5, "{transfer:?}: {t} came back as {back}");
+            }
+        }
+    }
+
+    #[test]
+    fn noise_floor_and_ceiling_narrow_the_range() {
+        let grid = quarter_grid();
+        let opts = RenderOptions {
+            normalization: Normalization::Fixed { min: 0.0, max: 255.0 },
+            noise_floor: Some(20.0),
+            ceiling: Some(220.0),
+            ..RenderOptions::default()
+        };
+        let shader = Shader::new(&opts, &grid);
+        assert_eq!(shader.range(), (20.0, 220.0));
+        assert_eq!(shader.color(19.0), None);
+        assert_eq!(shader.color(0.0), None);
+        assert_eq!(shader.color(f32::NAN), None);
+        assert_eq!(shader.color(20.0), Some(shader.at_position(0.0)));
+        assert_eq!(shader.color(120.0), Some(shader.at_position(0.5)));
+        assert_eq!(shader.color(250.0), Some(shader.at_position(1.0)));
+    }
+
+    #[test]
+    fn alpha_ramp_parsing() {
+        assert_eq!("opaque".parse::<AlphaRamp>().unwrap(), AlphaRamp::Opaque);
+        assert_eq!("off".parse::<AlphaRamp>().unwrap(), AlphaRamp::Opaque);
+        assert_eq!("ramp".parse::<AlphaRamp>().unwrap(), AlphaRamp::Ramp { start: 0.0, end: 1.0, gamma: 1.0 });
+        assert_eq!(
+            "ramp:0.1:0.6:2".parse::<AlphaRamp>().unwrap(),
+            AlphaRamp::Ramp { start: 0.1, end: 0.6, gamma: 2.0 }
+        );
+        for invalid in ["ramp:0.5", "ramp:0.6:0.1", "ramp:0:1.5", "ramp:0:1:0", "ramp:-0.1:1", "fade"] {
+            assert!(invalid.parse::<AlphaRamp>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn alpha_ramp_fades_weak_echoes() {
+        assert_eq!(AlphaRamp::Opaque.apply(0.0), 1.0);
+        let ramp = AlphaRamp::Ramp { start: 0.2, end: 0.6, gamma: 2.0 };
+        assert_eq!(ramp.apply(0.1), 0.0);
+        assert!((ramp.apply(0.4) - 0.25).abs() < 1e-6);
+        assert_eq!(ramp.apply(0.8), 1.0);
+
+        let grid = quarter_grid();
+        let opts = RenderOptions {
+            normalization: Normalization::Fixed { min: 0.0, max: 100.0 },
+            alpha: AlphaRamp::Ramp { start: 0.0, end: 1.0, gamma: 1.0 },
+            ..RenderOptions::default()
+        };
+        let shader = Shader::new(&opts, &grid);
+        let alpha = |v: f32| shader.color(v).unwrap()[3];
+        assert!(alpha(10.0) < 40, "{}", alpha(10.0));
+        assert!(alpha(50.0).abs_diff(128) <= 2, "{}", alpha(50.0));
+        assert_eq!(alpha(100.0), 255);
+    }
+
+    #[test]
+    fn bilinear_blends_neighbouring_cells() {
+        let flat = [0.0, 1.0, 2.0, 3.0];
+        // Bearing 90° lies between the centers of pulses 0 and 1
+        assert_eq!(sample_bilinear(&flat, 4, 1, 0.0, 0.5, 0.0), 0.5);
+        assert_eq!(sample_bilinear(&flat, 4, 1, 0.0, 0.5, 0.5), 0.0);
+        // Between the last pulse and the first
+        assert_eq!(sample_bilinear(&flat, 4, 1, 0.0, 0.0, 0.5), 1.5);
+    }
+
+    #[test]
+    fn bilinear_needs_half_the_weight_on_data() {
+        let flat = [1.0, f32::NAN, f32::NAN, f32::NAN];
+        let at = |deg: f64| {
+            let (sin, cos) = deg.to_radians().sin_cos();
+            sample_bilinear(&flat, 4, 1, 0.0, 0.5 * sin, 0.5 * cos)
+        };
+        assert_eq!(at(45.0), 1.0);
+        assert_eq!(at(89.0), 1.0);
+        assert!(at(91.0).is_nan());
+    }
+
+    #[test]
+    fn rotation_is_not_rounded_to_pulses() {
+        let opts = RenderOptions { size: 100, rotation: 45.0_f64.to_radians(), ..RenderOptions::default() };
+        let img = render_png(&quarter_grid(), &opts).unwrap();
+        // Echoes now cover 45-135°
+        assert_eq!(pixel_at(&img, 30.0)[3], 0);
+        assert_eq!(pixel_at(&img, 60.0)[3], 255);
+        assert_eq!(pixel_at(&img, 120.0)[3], 255);
+        assert_eq!(pixel_at(&img, 150.0)[3], 0);
+
+        // The centre of the echo pulse moved to 90°, where it is not blended
+        let opts = RenderOptions { interpolation: Interpolation::Bilinear, ..opts };
+        let img = render_png(&quarter_grid(), &opts).unwrap();
+        let top = CMap::Viridis.eval(1.0);
+        let near = pixel_at(&img, 90.0).iter().zip(top).all(|(&a, b)| a.abs_diff(b) <= 8);
+        assert!(near, "{:?} is not close to {:?}", pixel_at(&img, 90.0), top);
+    }
+
+    /// Grid of eight 45° pulses with echoes at 0-45° and `rest` elsewhere
+    fn wedge_grid(rest: f32) -> PolarGrid {
+        let mut bins = vec![vec![rest; 4]; 8];
+        bins[0] = vec![1.0; 4];
+        PolarGrid { theta_edges: (0..=8).map(|p| p as f64 * PI / 4.0).collect(), bins }
+    }
+
+    /// Whether the pixel at (x, y) of a 100 px image is within a pixel of the disk edge
+    fn near_disk_edge(x: u32, y: u32) -> bool {
+        ((x as f64 + 0.5 - 50.0).hypot(y as f64 + 0.5 - 50.0) - 50.0).abs() < 1.0
+    }
+
+    #[test]
+    fn antialias_softens_missing_data_edges() {
+        let opts = RenderOptions { size: 100, ..RenderOptions::default() };
+        let sharp = render_png(&wedge_grid(f32::NAN), &opts).unwrap();
+        assert!(sharp.pixels().all(|p| p[3] == 0 || p[3] == 255));
+        let smooth = render_png(&wedge_grid(f32::NAN), &RenderOptions { antialias: 4, ..opts }).unwrap();
+        // Pixel centres on the 45° edge are partly covered, as is the disk edge
+        for (x, y) in [(60, 39), (70, 29), (67, 3)] {
+            let alpha = smooth.get_pixel(x, y)[3];
+            assert!(alpha > 0 && alpha < 255, "({x}, {y}): {alpha}");
+        }
+        // Away from the disk edge and the 0° and 45° edges nothing changes
+        for (x, y, p) in smooth.enumerate_pixels() {
+            let on_wedge_edge = y < 50 && (x.abs_diff(50) <= 1 || (x + y).abs_diff(99) <= 1);
+            assert!(near_disk_edge(x, y) || on_wedge_edge || p == sharp.get_pixel(x, y), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn antialias_leaves_zero_echo_edges_sharp() {
+        let opts = RenderOptions { size: 100, ..RenderOptions::default() };
+        let sharp = render_png(&wedge_grid(0.0), &opts).unwrap();
+        let smooth = render_png(&wedge_grid(0.0), &RenderOptions { antialias: 4, ..opts }).unwrap();
+        for (x, y, p) in smooth.enumerate_pixels() {
+            assert!(near_disk_edge(x, y) || p == sharp.get_pixel(x, y), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn lookup_table_matches_per_pixel_sampling() {
+        let pulses = 36;
+        let grid = PolarGrid {
+            theta_edges: (0..=pulses).map(|p| p as f64 * 2.0 * PI / pulses as f64).collect(),
+            bins: (0..pulses).map(|p| (0..8).map(|b| (p * 8 + b + 1) as f32).collect()).collect(),
+        };
+        let flat: Vec<f32> = grid.bins.concat();
+        for rotation in [0.3, -1.234, 1000.0] {
+            let opts = RenderOptions { size: 64, rotation, ..RenderOptions::default() };
+            let img = render_png(&grid, &opts).unwrap();
+            let shader = Shader::new(&opts, &grid);
+            for (x, y, pixel) in img.enumerate_pixels() {
+                let (east, north) = ((x as f64 + 0.5 - 32.0) / 32.0, (32.0 - (y as f64 + 0.5)) / 32.0);
+                let v = sample(&flat, pulses, 8, Interpolation::Nearest, rotation, east, north);
+                let expected = shader.color(v).unwrap_or([0; 4]);
+                assert_eq!(pixel.0, expected, "({x}, {y}) at rotation {rotation}");
+            }
+        }
+    }
+
+    #[test]
+    fn whole_pulse_rotation_matches_turned_image() {
+        let opts = RenderOptions { size: 100, ..RenderOptions::default() };
+        let upright = render_png(&quarter_grid(), &opts).unwrap();
+        let turned = render_png(&quarter_grid(), &RenderOptions { rotation: PI / 2.0, ..opts }).unwrap();
+        assert_eq!(turned, image::imageops::rotate90(&upright));
+    }
+}
@@ -0,0 +1,859 @@
+//! Parsing of Furuno CSV captures into sweeps
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Error, Result};
+
+use crate::heading;
+
+/// A single radar sweep parsed from a Furuno CSV file.
+///
+/// Pulses with identical angles are merged by averaging, so `angles` holds
+/// unique values in no particular order.
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    /// Pulse azimuths in radians (0 = encoder zero, clockwise)
+    pub angles: Vec<f64>,
+    /// Echo values per pulse, one row for each entry of `angles`
+    pub bins: Vec<Vec<f32>>,
+    /// Range setting reported by the recorder
+    pub range_setting: i32,
+    /// Gain code reported by the recorder
+    pub gain: i32,
+    /// Range bins spanning the range setting, when the format has a scale column
+    pub scale: Option<f64>,
+    /// Capture timestamp, taken from the file stem
+    pub timestamp: String,
+    /// Number of the rotation within a file that spans several, from 1,
+    /// see [`read_rotations`]
+    pub rotation: Option<usize>,
+    /// Mean vessel heading in degrees, when the format has a heading column
+    pub heading: Option<f64>,
+}
+
+impl Sweep {
+    /// Default output filename: `<timestamp>_<gain>_<range>.png`, or
+    /// `<timestamp>_<gain>_<range>_r<NNN>.png` for a numbered rotation
+    pub fn output_name(&self) -> String {
+        match self.rotation {
+            Some(rotation) => rotation_output_name(&self.timestamp, self.gain, self.range_setting, rotation),
+            None => output_name(&self.timestamp, self.gain, self.range_setting),
+        }
+    }
+
+    /// Name of the file recording how many rotations a split file gave:
+    /// `<timestamp>_<gain>_<range>.rotations`
+    pub fn rotation_count_name(&self) -> String {
+        rotation_count_name(&self.timestamp, self.gain, self.range_setting)
+    }
+
+    /// Range at the outer edge of the last bin, in range setting units.
+    ///
+    /// Without a scale the range setting is taken to reach the last bin;
+    /// with one, `scale` bins span the range setting. Falls back to the
+    /// number of bins when the recorder gave no range setting.
+    pub fn range_max(&self) -> f64 {
+        range_max(self.range_setting, self.scale, self.bins.first().map_or(0, Vec::len))
+    }
+}
+
+/// Recorder settings of a capture, read without parsing the echo data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepInfo {
+    pub range_setting: i32,
+    pub gain: i32,
+    pub scale: Option<f64>,
+    /// Number of echo values in the first data row
+    pub n_bins: usize,
+    pub timestamp: String,
+}
+
+impl SweepInfo {
+    /// Output filename the full sweep would get, see [`Sweep::output_name`].
+    pub fn output_name(&self) -> String {
+        output_name(&self.timestamp, self.gain, self.range_setting)
+    }
+
+    /// Output filename of rotation `rotation` when the file is split, see
+    /// [`read_rotations`].
+    pub fn rotation_output_name(&self, rotation: usize) -> String {
+        rotation_output_name(&self.timestamp, self.gain, self.range_setting, rotation)
+    }
+
+    /// Name of the file recording the rotation count, see
+    /// [`Sweep::rotation_count_name`].
+    pub fn rotation_count_name(&self) -> String {
+        rotation_count_name(&self.timestamp, self.gain, self.range_setting)
+    }
+
+    /// Range at the edge of the image the full sweep would get, see [`Sweep::range_max`].
+    pub fn range_max(&self) -> f64 {
+        range_max(self.range_setting, self.scale, self.n_bins)
+    }
+}
+
+fn range_max(range_setting: i32, scale: Option<f64>, n_bins: usize) -> f64 {
+    match scale {
+        _ if range_setting <= 0 => n_bins as f64,
+        Some(scale) if n_bins > 0 => range_setting as f64 * n_bins as f64 / scale,
+        _ => range_setting as f64,
+    }
+}
+
+fn output_name(timestamp: &str, gain: i32, range_setting: i32) -> String {
+    format!("{}_{}_{}.png", timestamp, gain, range_setting)
+}
+
+fn rotation_output_name(timestamp: &str, gain: i32, range_setting: i32, rotation: usize) -> String {
+    format!("{}_{}_{}_r{:03}.png", timestamp, gain, range_setting, rotation)
+}
+
+fn rotation_count_name(timestamp: &str, gain: i32, range_setting: i32) -> String {
+    format!("{}_{}_{}.rotations", timestamp, gain, range_setting)
+}
+
+/// Capture timestamp taken from the file stem
+fn file_timestamp(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+/// A CSV column, by header name or zero-based index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    /// Header name, matched ignoring case and surrounding whitespace
+    Name(String),
+    Index(usize),
+}
+
+impl FromStr for Column {
+    type Err = Error;
+
+    /// A number is an index, anything else a header name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(anyhow!("Empty column name"));
+        }
+        Ok(s.parse().map_or_else(|_| Self::Name(s.to_string()), Self::Index))
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "\"{}\"", name),
+            Self::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+impl Column {
+    /// Position of the column in rows laid out like `header`
+    fn resolve(&self, header: Option<&[&str]>) -> Result<usize> {
+        match self {
+            Self::Index(index) => Ok(*index),
+            Self::Name(name) => {
+                let header = header.ok_or_else(|| anyhow!("column {} needs a header line", self))?;
+                header
+                    .iter()
+                    .position(|h| h.trim().eq_ignore_ascii_case(name))
+                    .ok_or_else(|| anyhow!("no column {} in header \"{}\"", self, header.join(",")))
+            }
+        }
+    }
+}
+
+/// Parse a field delimiter: a single character, or `tab` or `space`.
+pub fn parse_delimiter(s: &str) -> Result<char> {
+    match s {
+        "tab" | "\\t" | "\t" => Ok('\t'),
+        "space" => Ok(' '),
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c != '"' && c != '\n' => Ok(c),
+                _ => Err(anyhow!("Invalid delimiter: {s}")),
+            }
+        }
+    }
+}
+
+/// Column layout and delimiter of capture files.
+///
+/// The default matches the Furuno recorder:
+/// `Status,Scale,Range,Gain,Angle,EchoValues`, with echo values running to
+/// the end of each row. Named columns are looked up in the header line, so
+/// files whose columns are in a different order are read correctly, and
+/// files that lack a column fail instead of rendering garbage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvFormat {
+    /// Field separator
+    pub delimiter: char,
+    /// Whether the first line holds column names
+    pub header: bool,
+    /// Range setting
+    pub range: Column,
+    /// Gain code
+    pub gain: Column,
+    /// Encoder angle in ticks
+    pub angle: Column,
+    /// First echo value; the echoes run to the end of the row
+    pub echoes: Column,
+    /// Vessel heading in degrees, if the files record one
+    pub heading: Option<Column>,
+    /// Record status; when set, rows whose status is not [`NOMINAL_STATUS`]
+    /// are dropped
+    pub status: Option<Column>,
+    /// Number of range bins spanning the range setting; when set it scales
+    /// the range at the image edge, see [`Sweep::range_max`]
+    pub scale: Option<Column>,
+    /// Encoder ticks per revolution; angles run from 0 to one less
+    pub ticks_per_rev: u32,
+}
+
+/// Encoder resolution of the Furuno recorder
+pub const DEFAULT_TICKS_PER_REV: u32 = 8192;
+
+/// Status of rows recorded normally
+pub const NOMINAL_STATUS: i32 = 1;
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat {
+            delimiter: ',',
+            header: true,
+            range: Column::Name("Range".to_string()),
+            gain: Column::Name("Gain".to_string()),
+            angle: Column::Name("Angle".to_string()),
+            echoes: Column::Name("EchoValues".to_string()),
+            heading: None,
+            status: None,
+            scale: None,
+            ticks_per_rev: DEFAULT_TICKS_PER_REV,
+        }
+    }
+}
+
+impl CsvFormat {
+    /// Column positions for a file starting with `first_line`
+    fn layout(&self, first_line: &str) -> Result<Layout> {
+        let names: Option<Vec<&str>> = self.header.then(|| first_line.split(self.delimiter).collect());
+        let header = names.as_deref();
+        let layout = Layout {
+            range: self.range.resolve(header)?,
+            gain: self.gain.resolve(header)?,
+            angle: self.angle.resolve(header)?,
+            echoes: self.echoes.resolve(header)?,
+            heading: self.heading.as_ref().map(|c| c.resolve(header)).transpose()?,
+            status: self.status.as_ref().map(|c| c.resolve(header)).transpose()?,
+            scale: self.scale.as_ref().map(|c| c.resolve(header)).transpose()?,
+        };
+        let meta = [("range", layout.range), ("gain", layout.gain), ("angle", layout.angle)];
+        let optional = [("heading", layout.heading), ("status", layout.status), ("scale", layout.scale)];
+        let optional = optional.into_iter().filter_map(|(what, index)| index.map(|i| (what, i)));
+        for (what, index) in meta.into_iter().chain(optional) {
+            if index >= layout.echoes {
+                return Err(anyhow!(
+                    "{} column {} is not before the first echo column {}",
+                    what, index, layout.echoes
+                ));
+            }
+        }
+        Ok(layout)
+    }
+}
+
+/// Resolved column positions of a file
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    range: usize,
+    gain: usize,
+    angle: usize,
+    echoes: usize,
+    heading: Option<usize>,
+    status: Option<usize>,
+    scale: Option<usize>,
+}
+
+impl Layout {
+    /// Fields in a row with at least one echo value
+    fn min_fields(&self) -> usize {
+        self.echoes + 1
+    }
+}
+
+/// Why a data row of a CSV file is malformed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowProblem {
+    /// Fewer fields than the column layout needs; the row is skipped
+    ShortRow { fields: usize, needed: usize },
+    /// The first field of the row that is not a number; it reads as 0
+    NonNumeric { column: usize, value: String },
+    /// The first field of the row that reads as NaN or infinity; the row is
+    /// skipped
+    NonFinite { column: usize, value: String },
+    /// A different number of echo values than the first data row
+    BinCount { found: usize, expected: usize },
+    /// An encoder angle outside `[0, max]`
+    AngleOutOfRange { ticks: f64, max: f64 },
+    /// The range, gain or scale differs from the previous row; the sweep
+    /// keeps the first value
+    SettingChanged { setting: &'static str, from: f64, to: f64 },
+}
+
+impl fmt::Display for RowProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ShortRow { fields, needed } => {
+                write!(f, "short row: {} fields, need at least {}", fields, needed)
+            }
+            Self::NonNumeric { column, value } => {
+                write!(f, "non-numeric value \"{}\" in column {}", value, column)
+            }
+            Self::NonFinite { column, value } => {
+                write!(f, "non-finite value \"{}\" in column {}", value, column)
+            }
+            Self::BinCount { found, expected } => {
+                write!(f, "{} echo values, the first row has {}", found, expected)
+            }
+            Self::AngleOutOfRange { ticks, max } => {
+                write!(f, "angle {} outside [0, {}]", ticks, max)
+            }
+            Self::SettingChanged { setting, from, to } => {
+                write!(f, "{} changes from {} to {}", setting, from, to)
+            }
+        }
+    }
+}
+
+/// A malformed row of a CSV file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowIssue {
+    /// Line number in the file, starting at 1
+    pub line: usize,
+    pub problem: RowProblem,
+}
+
+impl fmt::Display for RowIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.problem)
+    }
+}
+
+/// Read the range setting, gain and scale of a CSV file.
+///
+/// Stops at the first row reporting all of them, so this is much cheaper
+/// than [`read_csv_with`] and gives the same values.
+pub fn read_info(path: &Path, format: &CsvFormat) -> Result<SweepInfo> {
+    let file = File::open(path).with_context(|| format!("reading {}", path.display()))?;
+    let mut lines = BufReader::new(file).lines();
+    let first = lines
+        .next()
+        .transpose()
+        .with_context(|| format!("reading {}", path.display()))?
+        .unwrap_or_default();
+    let layout = format.layout(&first).with_context(|| format!("reading {}", path.display()))?;
+    let first_row = (!format.header).then_some(first);
+
+    let mut range_setting = 0i32;
+    let mut gain = 0i32;
+    let mut scale = 0.0f64;
+    let mut n_bins = None;
+    for line in first_row.into_iter().map(Ok).chain(lines) {
+        let line = line.with_context(|| format!("reading {}", path.display()))?;
+        let parts: Vec<&str> = line.splitn(layout.min_fields() + 1, format.delimiter).collect();
+        if parts.len() < layout.min_fields() {
+            continue;
+        }
+        if let Some(column) = layout.status
+            && parts[column].trim().parse() != Ok(NOMINAL_STATUS)
+        {
+            continue;
+        }
+        n_bins.get_or_insert_with(|| line.split(format.delimiter).count() - layout.echoes);
+        if range_setting == 0 {
+            range_setting = parts[layout.range].trim().parse().unwrap_or(0);
+        }
+        if gain == 0 {
+            gain = parts[layout.gain].trim().parse().unwrap_or(0);
+        }
+        if let Some(column) = layout.scale
+            && scale == 0.0
+        {
+            scale = parts[column].trim().parse().unwrap_or(0.0);
+        }
+        if range_setting != 0 && gain != 0 && (layout.scale.is_none() || scale != 0.0) {
+            break;
+        }
+    }
+
+    Ok(SweepInfo {
+        range_setting,
+        gain,
+        scale: (scale > 0.0).then_some(scale),
+        n_bins: n_bins.unwrap_or(0),
+        timestamp: file_timestamp(path),
+    })
+}
+
+/// Read a Furuno CSV file into a [`Sweep`], using the default [`CsvFormat`].
+pub fn read_csv(path: &Path) -> Result<Sweep> {
+    read_csv_with(path, &CsvFormat::default())
+}
+
+/// Read a CSV file laid out as described by `format` into a [`Sweep`].
+///
+/// Malformed rows are handled leniently: rows with too few fields are
+/// skipped and fields that are not numbers read as 0. Use
+/// [`read_csv_checked`] to find out about them.
+///
+/// With a status column, rows with a status other than [`NOMINAL_STATUS`]
+/// are dropped. The range setting, gain and scale are taken from the first
+/// row that reports them; later changes are listed as
+/// [`RowProblem::SettingChanged`] by [`read_csv_checked`].
+pub fn read_csv_with(path: &Path, format: &CsvFormat) -> Result<Sweep> {
+    read_csv_checked(path, format).map(|(sweep, _)| sweep)
+}
+
+/// Read a CSV file like [`read_csv_with`], also returning its malformed rows.
+pub fn read_csv_checked(path: &Path, format: &CsvFormat) -> Result<(Sweep, Vec<RowIssue>)> {
+    let (rows, issues) = read_rows(path, format)?;
+    Ok((build_sweep(rows, file_timestamp(path), None), issues))
+}
+
+/// Read a CSV file that may span several antenna rotations into one sweep
+/// per rotation, also returning its malformed rows.
+///
+/// A new rotation starts wherever the angle falls back by more than half a
+/// revolution from the previous row. The first and last rotations of a file
+/// are usually partial. A file holding a single rotation gives one sweep
+/// without a [`rotation`](Sweep::rotation) number, like [`read_csv_checked`].
+pub fn read_rotations(path: &Path, format: &CsvFormat) -> Result<(Vec<Sweep>, Vec<RowIssue>)> {
+    let (rows, issues) = read_rows(path, format)?;
+    let timestamp = file_timestamp(path);
+
+    let mut rotations: Vec<Vec<Row>> = Vec::new();
+    let mut previous = f64::NEG_INFINITY;
+    for row in rows {
+        if row.angle < previous - PI || rotations.is_empty() {
+            rotations.push(Vec::new());
+        }
+        previous = row.angle;
+        rotations.last_mut().unwrap().push(row);
+    }
+
+    let numbered = rotations.len() > 1;
+    let sweeps = rotations
+        .into_iter()
+        .zip(1..)
+        .map(|(rows, number)| build_sweep(rows, timestamp.clone(), numbered.then_some(number)))
+        .collect();
+    Ok((sweeps, issues))
+}
+
+/// A data row that passed the status filter
+struct Row {
+    /// Azimuth in radians
+    angle: f64,
+    bins: Vec<f32>,
+    range: i32,
+    gain: i32,
+    scale: f64,
+    heading: Option<f64>,
+}
+
+/// Read the data rows of a CSV file in file order, with its malformed rows.
+fn read_rows(path: &Path, format: &CsvFormat) -> Result<(Vec<Row>, Vec<RowIssue>)> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    let mut lines = text.lines().peekable();
+    let first = lines
+        .peek()
+        .ok_or_else(|| anyhow!("empty CSV: {}", path.display()))?;
+    let layout = format.layout(first).with_context(|| format!("reading {}", path.display()))?;
+    if format.header {
+        lines.next();
+    }
+    let first_row = if format.header { 2 } else { 1 };
+    let ticks_per_rev = format.ticks_per_rev.max(1) as f64;
+    let max_ticks = ticks_per_rev - 1.0;
+
+    let mut rows = Vec::new();
+    // Last non-zero value of each setting, to report changes once
+    let mut last = [0.0f64; 3];
+    let mut issues = Vec::new();
+    let mut expected_bins = None;
+
+    for (line_number, line) in (first_row..).zip(lines) {
+        let mut issue = |problem| issues.push(RowIssue { line: line_number, problem });
+        let parts: Vec<&str> = line.split(format.delimiter).collect();
+        if parts.len() < layout.min_fields() {
+            issue(RowProblem::ShortRow {
+                fields: parts.len(),
+                needed: layout.min_fields(),
+            });
+            continue; // Skip malformed lines
+        }
+
+        let mut non_numeric = None;
+        // Rows recorded with another status are dropped whatever their values
+        let status: Option<i32> = layout.status.map(|column| field(&parts, column, &mut non_numeric));
+        if status.is_some_and(|status| status != NOMINAL_STATUS) {
+            if let Some(column) = non_numeric {
+                issue(RowProblem::NonNumeric {
+                    column,
+                    value: parts[column].to_string(),
+                });
+            }
+            continue;
+        }
+        let angle_ticks: f64 = field(&parts, layout.angle, &mut non_numeric);
+        let range: i32 = field(&parts, layout.range, &mut non_numeric);
+        let gain: i32 = field(&parts, layout.gain, &mut non_numeric);
+        let scale: f64 = layout.scale.map_or(0.0, |column| field(&parts, column, &mut non_numeric));
+        let heading: Option<f64> = layout.heading.map(|column| field(&parts, column, &mut non_numeric));
+        let bins: Vec<f32> = (layout.echoes..parts.len())
+            .map(|column| field(&parts, column, &mut non_numeric))
+            .collect();
+
+        if let Some(column) = non_numeric {
+            issue(RowProblem::NonNumeric {
+                column,
+                value: parts[column].to_string(),
+            });
+        }
+        let mut finite = [(layout.angle, angle_ticks)]
+            .into_iter()
+            .chain(layout.scale.map(|column| (column, scale)))
+            .chain(layout.heading.zip(heading))
+            .chain((layout.echoes..).zip(bins.iter().map(|&v| v as f64)));
+        if let Some((column, _)) = finite.find(|(_, v)| !v.is_finite()) {
+            issue(RowProblem::NonFinite {
+                column,
+                value: parts[column].to_string(),
+            });
+            continue;
+        }
+        let settings = [("range", range as f64), ("gain", gain as f64), ("scale", scale)];
+        for ((setting, value), last) in settings.into_iter().zip(&mut last) {
+            if value == 0.0 {
+                continue;
+            }
+            if *last != 0.0 && value != *last {
+                issue(RowProblem::SettingChanged { setting, from: *last, to: value });
+            }
+            *last = value;
+        }
+        if !(0.0..=max_ticks).contains(&angle_ticks) {
+            issue(RowProblem::AngleOutOfRange {
+                ticks: angle_ticks,
+                max: max_ticks,
+            });
+        }
+        match expected_bins {
+            None => expected_bins = Some(bins.len()),
+            Some(expected) if expected != bins.len() => issue(RowProblem::BinCount {
+                found: bins.len(),
+                expected,
+            }),
+            Some(_) => {}
+        }
+
+        rows.push(Row {
+            angle: angle_ticks * (2.0 * PI / ticks_per_rev),
+            bins,
+            range,
+            gain,
+            scale,
+            heading,
+        });
+    }
+
+    if rows.is_empty() {
+        return Err(anyhow!("no data rows in {}", path.display()));
+    }
+    Ok((rows, issues))
+}
+
+/// Merge `rows` into a sweep, taking each setting from the first row that
+/// reports it.
+fn build_sweep(rows: Vec<Row>, timestamp: String, rotation: Option<usize>) -> Sweep {
+    let first_nonzero = |value: fn(&Row) -> f64| rows.iter().map(value).find(|&v| v != 0.0).unwrap_or(0.0);
+    let range_setting = first_nonzero(|row| row.range as f64) as i32;
+    let gain = first_nonzero(|row| row.gain as f64) as i32;
+    let scale = first_nonzero(|row| row.scale);
+    let heading = heading::mean_heading(rows.iter().filter_map(|row| row.heading));
+
+    // Merge duplicate angles by averaging
+    let mut angle_map: HashMap<u64, (Vec<Vec<f32>>, f64)> = HashMap::new();
+
+    for row in rows {
+        let angle_key = (row.angle * 100000.0).round() as u64;
+        angle_map.entry(angle_key)
+            .or_insert_with(|| (Vec::new(), row.angle))
+            .0.push(row.bins);
+    }
+
+    let mut angles = Vec::new();
+    let mut bins = Vec::new();
+
+    for (_key, (mut bin_rows, angle)) in angle_map.into_iter() {
+        angles.push(angle);
+        if bin_rows.len() == 1 {
+            bins.push(bin_rows.pop().unwrap());
+        } else {
+            let n_bins = bin_rows[0].len();
+            let mut avg_bins = vec![0.0f32; n_bins];
+            for bin_row in &bin_rows {
+                for (i, &val) in bin_row.iter().enumerate() {
+                    if i < avg_bins.len() {
+                        avg_bins[i] += val;
+                    }
+                }
+            }
+            for val in &mut avg_bins {
+                *val /= bin_rows.len() as f32;
+            }
+            bins.push(avg_bins);
+        }
+    }
+
+    Sweep {
+        angles,
+        bins,
+        range_setting,
+        gain,
+        scale: (scale > 0.0).then_some(scale),
+        timestamp,
+        rotation,
+        heading,
+    }
+}
+
+/// Parse field `column` of a row, or 0 when it is not a number.
+///
+/// The first such column is kept in `non_numeric`.
+fn field<T: FromStr + Default>(parts: &[&str], column: usize, non_numeric: &mut Option<usize>) -> T {
+    parts[column].trim().parse().unwrap_or_else(|_| {
+        non_numeric.get_or_insert(column);
+        T::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    /// Write `text` to a CSV named `<name>.csv` in a directory of its own
+    fn write_csv(name: &str, text: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("radar_plotter_sweep_{}_{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{name}.csv"));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn column_and_delimiter_parsing() {
+        assert_eq!("3".parse::<Column>().unwrap(), Column::Index(3));
+        assert_eq!(" Angle ".parse::<Column>().unwrap(), Column::Name("Angle".to_string()));
+        assert!("  ".parse::<Column>().is_err());
+        assert_eq!(parse_delimiter("tab").unwrap(), '\t');
+        assert_eq!(parse_delimiter("space").unwrap(), ' ');
+        assert_eq!(parse_delimiter(";").unwrap(), ';');
+        assert!(parse_delimiter(";;").is_err());
+        assert!(parse_delimiter("\"").is_err());
+    }
+
+    #[test]
+    fn columns_found_by_header_name() {
+        let path = write_csv(
+            "20240101120001",
+            "angle;GAIN;range;echo\n\
+             2048;60;3;1;2;3\n\
+             4096;60;3;4;5;6\n",
+        );
+        let format = CsvFormat {
+            delimiter: ';',
+            echoes: Column::Name("echo".to_string()),
+            ..CsvFormat::default()
+        };
+        let sweep = read_csv_with(&path, &format).unwrap();
+        assert_eq!((sweep.range_setting, sweep.gain), (3, 60));
+        let mut rows: Vec<(f64, Vec<f32>)> = sweep.angles.into_iter().zip(sweep.bins).collect();
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(rows, [(PI / 2.0, vec![1.0, 2.0, 3.0]), (PI, vec![4.0, 5.0, 6.0])]);
+
+        let info = read_info(&path, &format).unwrap();
+        assert_eq!((info.range_setting, info.gain, info.n_bins), (3, 60, 3));
+        assert_eq!(info.output_name(), "20240101120001_60_3.png");
+    }
+
+    #[test]
+    fn columns_by_index_without_header() {
+        let path = write_csv("20240101120002", "1,3,60,0,7,8\n");
+        let format = CsvFormat {
+            header: false,
+            range: Column::Index(1),
+            gain: Column::Index(2),
+            angle: Column::Index(3),
+            echoes: Column::Index(4),
+            ..CsvFormat::default()
+        };
+        let sweep = read_csv_with(&path, &format).unwrap();
+        assert_eq!((sweep.range_setting, sweep.gain), (3, 60));
+        assert_eq!(sweep.bins, [vec![7.0, 8.0]]);
+    }
+
+    #[test]
+    fn missing_or_misplaced_columns_fail() {
+        let path = write_csv("20240101120003", "Range,Gain,Bearing,EchoValues\n3,60,0,1\n");
+        let error = read_csv(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("no column \"Angle\""), "{:#}", error);
+
+        let format = CsvFormat { echoes: Column::Index(1), angle: Column::Index(2), ..CsvFormat::default() };
+        let error = read_csv_with(&path, &format).unwrap_err();
+        assert!(format!("{:#}", error).contains("not before the first echo column"), "{:#}", error);
+    }
+
+    #[test]
+    fn angles_scale_with_ticks_per_rev() {
+        let path = write_csv(
+            "20240101120005",
+            "Range,Gain,Angle,EchoValues\n3,60,1024,1\n3,60,4095,2\n3,60,4096,3\n",
+        );
+        let format = CsvFormat { ticks_per_rev: 4096, ..CsvFormat::default() };
+        let (sweep, issues) = read_csv_checked(&path, &format).unwrap();
+        let mut angles = sweep.angles;
+        angles.sort_by(f64::total_cmp);
+        assert_eq!(angles, [PI / 2.0, 4095.0 * 2.0 * PI / 4096.0, 2.0 * PI]);
+        assert_eq!(issues[0].problem, RowProblem::AngleOutOfRange { ticks: 4096.0, max: 4095.0 });
+    }
+
+    #[test]
+    fn malformed_rows_are_flagged() {
+        let path = write_csv(
+            "20240101120004",
+            "Status,Scale,Range,Gain,Angle,EchoValues\n\
+             1,496,3,60,0,1,2\n\
+             1,496,3\n\
+             1,496,3,60,x,1,2\n\
+             1,496,3,60,9000,1,2\n\
+             1,496,3,60,30,1,2,3\n",
+        );
+        let (sweep, issues) = read_csv_checked(&path, &CsvFormat::default()).unwrap();
+        // Only the short row is dropped; the bad angle reads as 0
+        assert_eq!(sweep.angles.len(), 3);
+        let problems: Vec<_> = issues.into_iter().map(|i| (i.line, i.problem)).collect();
+        assert_eq!(
+            problems,
+            [
+                (3, RowProblem::ShortRow { fields: 3, needed: 6 }),
+                (4, RowProblem::NonNumeric { column: 4, value: "x".to_string() }),
+                (5, RowProblem::AngleOutOfRange { ticks: 9000.0, max: 8191.0 }),
+                (6, RowProblem::BinCount { found: 3, expected: 2 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn status_filter_and_scale() {
+        let path = write_csv(
+            "20240101120008",
+            "Status,Scale,Range,Gain,Angle,EchoValues\n\
+             1,4,3,60,0,1,2\n\
+             0,4,3,60,2048,9,9\n\
+             2,?,3,60,nan,9,inf\n\
+             1,4,6,60,4096,3,4\n\
+             ok,4,3,60,0,9,9\n",
+        );
+        let format = CsvFormat {
+            status: Some(Column::Name("Status".to_string())),
+            scale: Some(Column::Name("Scale".to_string())),
+            ..CsvFormat::default()
+        };
+        let (sweep, issues) = read_csv_checked(&path, &format).unwrap();
+        // Rows with another status are dropped unchecked and the first range is kept
+        assert_eq!(sweep.bins.len(), 2);
+        assert!(sweep.bins.iter().all(|bins| bins[0] != 9.0));
+        assert_eq!(sweep.range_setting, 3);
+        let problems: Vec<_> = issues.into_iter().map(|i| (i.line, i.problem)).collect();
+        assert_eq!(
+            problems,
+            [
+                (5, RowProblem::SettingChanged { setting: "range", from: 3.0, to: 6.0 }),
+                (6, RowProblem::NonNumeric { column: 0, value: "ok".to_string() }),
+            ]
+        );
+        // 4 bins span range 3, so the 2 recorded bins reach 1.5
+        assert_eq!(sweep.scale, Some(4.0));
+        assert_eq!(sweep.range_max(), 1.5);
+        assert_eq!(read_info(&path, &format).unwrap().range_max(), 1.5);
+        // Without a scale column the range setting reaches the last bin
+        assert_eq!(read_csv_with(&path, &CsvFormat::default()).unwrap().range_max(), 3.0);
+    }
+
+    #[test]
+    fn wrapped_angles_start_new_rotations() {
+        let path = write_csv(
+            "20240101120006",
+            "Range,Gain,Angle,EchoValues\n\
+             3,60,4000,1\n3,60,8000,2\n\
+             3,60,100,3\n3,60,4096,4\n3,60,8100,5\n\
+             3,60,50,6\n",
+        );
+        let (sweeps, issues) = read_rotations(&path, &CsvFormat::default()).unwrap();
+        assert!(issues.is_empty());
+        let rows: Vec<(Option<usize>, usize)> = sweeps.iter().map(|s| (s.rotation, s.angles.len())).collect();
+        assert_eq!(rows, [(Some(1), 2), (Some(2), 3), (Some(3), 1)]);
+        assert_eq!(sweeps[1].output_name(), "20240101120006_60_3_r002.png");
+        assert_eq!(sweeps[1].rotation_count_name(), "20240101120006_60_3.rotations");
+    }
+
+    #[test]
+    fn single_rotation_is_not_numbered() {
+        // Small steps back, as from encoder jitter, don't split
+        let path = write_csv(
+            "20240101120007",
+            "Range,Gain,Angle,EchoValues\n3,60,10,1\n3,60,4000,2\n3,60,3990,3\n",
+        );
+        let (sweeps, _) = read_rotations(&path, &CsvFormat::default()).unwrap();
+        assert_eq!(sweeps.len(), 1);
+        assert_eq!(sweeps[0].rotation, None);
+        assert_eq!(sweeps[0].output_name(), "20240101120007_60_3.png");
+    }
+
+    #[test]
+    fn non_finite_rows_are_skipped() {
+        let path = write_csv(
+            "20240101120000",
+            "Status,Scale,Range,Gain,Angle,EchoValues\n\
+             1,496,3,60,0,1,2\n\
+             1,496,3,60,nan,3,4\n\
+             1,496,3,60,20,inf,4\n\
+             1,496,3,60,30,5,6\n",
+        );
+        let (sweep, issues) = read_csv_checked(&path, &CsvFormat::default()).unwrap();
+        assert_eq!(sweep.angles.len(), 2);
+        assert!(sweep.angles.iter().all(|a| a.is_finite()));
+        let problems: Vec<_> = issues.iter().map(|i| (i.line, i.problem.clone())).collect();
+        assert_eq!(
+            problems,
+            [
+                (3, RowProblem::NonFinite { column: 4, value: "nan".to_string() }),
+                (4, RowProblem::NonFinite { column: 5, value: "inf".to_string() }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Capture timestamps parsed from CSV filenames
+
+use std::fmt;
+
+/// A UTC capture time parsed from a filename.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millis: u32,
+}
+
+impl Timestamp {
+    /// Parse a timestamp from a filename stem.
+    ///
+    /// All digits of the stem are concatenated and read as
+    /// `YYYYMMDDhhmmss` followed by optional fractional seconds, so
+    /// `20240101120000123`, `2024-01-01_12-00-00.123` and
+    /// `20240101_120000` are all accepted.
+    pub fn parse_filename(stem: &str) -> Option<Self> {
+        let digits: Vec<u32> = stem.chars().filter_map(|c| c.to_digit(10)).collect();
+        if digits.len() < 14 {
+            return None;
+        }
+        let num = |range: std::ops::Range<usize>| digits[range].iter().fold(0u32, |acc, d| acc * 10 + d);
+
+        let mut millis = 0;
+        for i in 0..3 {
+            millis = millis * 10 + digits.get(14 + i).copied().unwrap_or(0);
+        }
+
+        let ts = Timestamp {
+            year: num(0..4) as i32,
+            month: num(4..6),
+            day: num(6..8),
+            hour: num(8..10),
+            minute: num(10..12),
+            second: num(12..14),
+            millis,
+        };
+        let valid = (1..=12).contains(&ts.month)
+            && (1..=31).contains(&ts.day)
+            && ts.hour < 24
+            && ts.minute < 60
+            && ts.second < 61;
+        valid.then_some(ts)
+    }
+
+    /// ISO 8601 / RFC 3339 form in UTC, e.g. `2024-01-01T12:00:00.123Z`.
+    pub fn to_rfc3339(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.millis
+        )
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn unix_seconds(&self) -> f64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs = days * 86_400 + (self.hour * 3600 + self.minute * 60 + self.second) as i64;
+        secs as f64 + self.millis as f64 / 1000.0
+    }
+}
+
+impl fmt::Display for Timestamp {
+    /// `YYYY-MM-DD hh:mm:ss`, with `.fff` when there are milliseconds.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )?;
+        if self.millis > 0 {
+            write!(f, ".{:03}", self.millis)?;
+        }
+        Ok(())
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year } as i64;
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_with_separators_and_millis() {
+        let plain = Timestamp::parse_filename("20240101120000123").unwrap();
+        assert_eq!(Timestamp::parse_filename("2024-01-01_12-00-00.123"), Some(plain));
+        assert_eq!(plain.to_rfc3339(), "2024-01-01T12:00:00.123Z");
+        assert_eq!(Timestamp::parse_filename("capture_20240101_120000").unwrap().millis, 0);
+    }
+
+    #[test]
+    fn rejects_short_and_invalid_stems() {
+        assert_eq!(Timestamp::parse_filename("2024010112"), None);
+        assert_eq!(Timestamp::parse_filename("20241301120000"), None);
+        assert_eq!(Timestamp::parse_filename("20240101250000"), None);
+    }
+
+    #[test]
+    fn unix_seconds() {
+        let ts = Timestamp::parse_filename("20240101000000").unwrap();
+        assert_eq!(ts.unix_seconds(), 1_704_067_200.0);
+        let leap = Timestamp::parse_filename("20240229235959500").unwrap();
+        assert_eq!(leap.unix_seconds(), 1_709_251_199.5);
+    }
+}
@@ -0,0 +1,136 @@
+//! Checking of CSV files for malformed rows
+//!
+//! By default malformed rows are read leniently, so a corrupt capture can
+//! render as a plausible image. With validation enabled every rendered file
+//! is checked with [`read_csv_checked`](crate::sweep::read_csv_checked) and a
+//! report of the problems is written to each output folder. Changes of the
+//! range setting, gain or scale within a file are always reported, since
+//! only the first value is used for the image.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Error, Result};
+
+use crate::sweep::RowIssue;
+
+/// Report filename inside the output folder
+pub const REPORT_NAME: &str = "validation_report.txt";
+
+/// Rows listed per file in the report; the rest are only counted
+const MAX_LISTED: usize = 100;
+
+/// What to do with files that have malformed rows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Read malformed rows leniently and only report setting changes
+    #[default]
+    Off,
+    /// Render the file and list its malformed rows in the report
+    Warn,
+    /// Fail the file and list its malformed rows in the report
+    Strict,
+}
+
+impl FromStr for ValidationMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "strict" => Ok(Self::Strict),
+            _ => Err(anyhow!("Unknown validation mode: {s}")),
+        }
+    }
+}
+
+/// Malformed rows of one checked file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileReport {
+    /// CSV filename
+    pub file: String,
+    pub issues: Vec<RowIssue>,
+}
+
+/// Number of reports with at least one flagged row.
+pub fn files_with_issues(reports: &[FileReport]) -> usize {
+    reports.iter().filter(|r| !r.issues.is_empty()).count()
+}
+
+/// Summary of `reports` for the folder `folder_name`, files in name order.
+pub fn report_text(folder_name: &str, reports: &[FileReport]) -> String {
+    let mut sorted: Vec<&FileReport> = reports.iter().filter(|r| !r.issues.is_empty()).collect();
+    sorted.sort_by(|a, b| a.file.cmp(&b.file));
+    let rows: usize = sorted.iter().map(|r| r.issues.len()).sum();
+
+    let mut text = String::new();
+    let _ = writeln!(text, "Validation report for {}", folder_name);
+    let _ = writeln!(
+        text,
+        "{} files checked, {} with flagged rows, {} flagged rows",
+        reports.len(),
+        sorted.len(),
+        rows
+    );
+    for report in sorted {
+        let _ = writeln!(text);
+        let _ = writeln!(text, "{}: {} flagged rows", report.file, report.issues.len());
+        for issue in report.issues.iter().take(MAX_LISTED) {
+            let _ = writeln!(text, "  {}", issue);
+        }
+        if report.issues.len() > MAX_LISTED {
+            let _ = writeln!(text, "  ... and {} more", report.issues.len() - MAX_LISTED);
+        }
+    }
+    text
+}
+
+/// Write the report of `folder_name` into `output_dir`.
+pub fn write_report(output_dir: &Path, folder_name: &str, reports: &[FileReport]) -> Result<()> {
+    let path = output_dir.join(REPORT_NAME);
+    fs::write(&path, report_text(folder_name, reports))
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sweep::RowProblem;
+
+    #[test]
+    fn report_lists_flagged_files_in_name_order() {
+        let issue = |line| RowIssue { line, problem: RowProblem::ShortRow { fields: 2, needed: 6 } };
+        let reports = [
+            FileReport { file: "b.csv".to_string(), issues: vec![issue(4)] },
+            FileReport { file: "c.csv".to_string(), issues: Vec::new() },
+            FileReport { file: "a.csv".to_string(), issues: vec![issue(2), issue(3)] },
+        ];
+        assert_eq!(files_with_issues(&reports), 2);
+        assert_eq!(
+            report_text("capture", &reports),
+            "Validation report for capture\n\
+             3 files checked, 2 with flagged rows, 3 flagged rows\n\
+             \n\
+             a.csv: 2 flagged rows\n\
+             \x20 line 2: short row: 2 fields, need at least 6\n\
+             \x20 line 3: short row: 2 fields, need at least 6\n\
+             \n\
+             b.csv: 1 flagged rows\n\
+             \x20 line 4: short row: 2 fields, need at least 6\n"
+        );
+    }
+
+    #[test]
+    fn report_truncates_long_lists() {
+        let issues = (1..=MAX_LISTED + 5)
+            .map(|line| RowIssue { line, problem: RowProblem::ShortRow { fields: 2, needed: 6 } })
+            .collect();
+        let text = report_text("capture", &[FileReport { file: "a.csv".to_string(), issues }]);
+        assert!(text.ends_with("  ... and 5 more\n"));
+        assert_eq!(text.lines().filter(|l| l.starts_with("  line ")).count(), MAX_LISTED);
+    }
+}
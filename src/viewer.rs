@@ -0,0 +1,269 @@
+//! Interactive inspection of a single sweep
+//!
+//! A [`SweepViewer`] holds one regularized sweep and a zoomable, pannable
+//! view of it. The view is rendered at whatever size the window has, so range
+//! bins stay sharp at any zoom, and any point of the view can be mapped back
+//! to range, bearing and grid indices.
+
+use std::f64::consts::PI;
+use std::path::Path;
+
+use anyhow::Result;
+use image::{Rgba, RgbaImage};
+
+use crate::grid::{regularize, GridOptions, PolarGrid};
+use crate::render::{RenderOptions, Shader};
+use crate::sweep::{read_csv_with, CsvFormat, Sweep};
+
+/// Closest zoom, relative to the whole disk filling the view
+const MAX_ZOOM: f64 = 512.0;
+/// Farthest zoom; below 1 the whole disk is smaller than the view
+const MIN_ZOOM: f64 = 0.5;
+
+/// What lies under a point of the view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Readout {
+    /// Range in the recorder's range units (bins when it reports none)
+    pub range: f64,
+    /// Bearing in degrees clockwise from north
+    pub bearing_deg: f64,
+    /// Pulse (row) of the regularized grid
+    pub pulse: usize,
+    /// Range bin (column) of the regularized grid
+    pub bin: usize,
+    /// Echo value of the grid cell, `NaN` where there is no data
+    pub value: f32,
+}
+
+/// A sweep with the current zoom and pan of an interactive view.
+///
+/// View coordinates are in pixels from the top-left corner of a
+/// `width` x `height` view. At zoom 1 the disk fits the smaller side.
+#[derive(Debug, Clone)]
+pub struct SweepViewer {
+    sweep: Sweep,
+    grid: PolarGrid,
+    shader: Shader,
+    /// Disk point at the view center, in disk radii east and north
+    center: (f64, f64),
+    zoom: f64,
+}
+
+impl SweepViewer {
+    /// Read and regularize `path` for viewing.
+    pub fn open(
+        path: &Path,
+        format: &CsvFormat,
+        grid_opts: &GridOptions,
+        render_opts: &RenderOptions,
+    ) -> Result<Self> {
+        let sweep = read_csv_with(path, format)?;
+        let grid = regularize(&sweep, grid_opts);
+        Ok(Self::new(sweep, grid, render_opts))
+    }
+
+    /// Viewer coloring `grid` like `render_opts`; size, rotation and
+    /// sampling are those of the view.
+    pub fn new(sweep: Sweep, grid: PolarGrid, render_opts: &RenderOptions) -> Self {
+        SweepViewer {
+            shader: Shader::new(render_opts, &grid),
+            sweep,
+            grid,
+            center: (0.0, 0.0),
+            zoom: 1.0,
+        }
+    }
+
+    pub fn sweep(&self) -> &Sweep {
+        &self.sweep
+    }
+
+    pub fn grid(&self) -> &PolarGrid {
+        &self.grid
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Range at the disk edge, see [`Sweep::range_max`]
+    pub fn range_max(&self) -> f64 {
+        self.sweep.range_max()
+    }
+
+    /// Show the whole disk again.
+    pub fn reset(&mut self) {
+        self.center = (0.0, 0.0);
+        self.zoom = 1.0;
+    }
+
+    /// Multiply the zoom by `factor`, keeping the point under `(x, y)` in place.
+    pub fn zoom_at(&mut self, x: f64, y: f64, factor: f64, width: f64, height: f64) {
+        let before = self.to_disk(x, y, width, height);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let after = self.to_disk(x, y, width, height);
+        self.center.0 += before.0 - after.0;
+        self.center.1 += before.1 - after.1;
+        self.clamp_center();
+    }
+
+    /// Move the view contents by `(dx, dy)` pixels.
+    pub fn pan(&mut self, dx: f64, dy: f64, width: f64, height: f64) {
+        let scale = self.scale(width, height);
+        self.center.0 -= dx / scale;
+        self.center.1 += dy / scale;
+        self.clamp_center();
+    }
+
+    /// Grid cell and polar position under `(x, y)`, `None` outside the disk.
+    pub fn readout(&self, x: f64, y: f64, width: f64, height: f64) -> Option<Readout> {
+        let (east, north) = self.to_disk(x, y, width, height);
+        let (pulse, bin) = self.cell(east, north)?;
+        let r_norm = east.hypot(north);
+        let bearing = east.atan2(north).rem_euclid(2.0 * PI);
+        Some(Readout {
+            range: r_norm * self.range_max(),
+            bearing_deg: bearing.to_degrees(),
+            pulse,
+            bin,
+            value: self.grid.bins[pulse].get(bin).copied().unwrap_or(f32::NAN),
+        })
+    }
+
+    /// Render the current view at `width` x `height` pixels.
+    ///
+    /// Colors and transparency match [`render_png`](crate::render::render_png)
+    /// with the render options the viewer was opened with.
+    pub fn render(&self, width: u32, height: u32) -> RgbaImage {
+        let mut img = RgbaImage::new(width, height);
+        if self.shader.is_empty() {
+            return img;
+        }
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let (east, north) = self.to_disk(x as f64 + 0.5, y as f64 + 0.5, width as f64, height as f64);
+            let Some((pulse, bin)) = self.cell(east, north) else {
+                continue;
+            };
+            let v = self.grid.bins[pulse].get(bin).copied().unwrap_or(f32::NAN);
+            if let Some(color) = self.shader.color(v) {
+                *pixel = Rgba(color);
+            }
+        }
+        img
+    }
+
+    /// View pixels per disk radius
+    fn scale(&self, width: f64, height: f64) -> f64 {
+        width.min(height) / 2.0 * self.zoom
+    }
+
+    /// View position to disk coordinates, in radii east and north of the center
+    fn to_disk(&self, x: f64, y: f64, width: f64, height: f64) -> (f64, f64) {
+        let scale = self.scale(width, height);
+        (
+            self.center.0 + (x - width / 2.0) / scale,
+            self.center.1 - (y - height / 2.0) / scale,
+        )
+    }
+
+    /// Grid cell at a disk position, using the same binning as [`PolarLut`](crate::lut::PolarLut)
+    fn cell(&self, east: f64, north: f64) -> Option<(usize, usize)> {
+        let pulses = self.grid.pulses();
+        let n_bins = self.grid.n_bins();
+        let r_norm = east.hypot(north);
+        if pulses == 0 || r_norm > 1.0 {
+            return None;
+        }
+        let theta = east.atan2(north).rem_euclid(2.0 * PI);
+        let pulse = ((theta / (2.0 * PI)) * pulses as f64).floor() as usize % pulses;
+        let bin = (r_norm * n_bins as f64).floor() as usize;
+        (bin < n_bins).then_some((pulse, bin))
+    }
+
+    /// Keep the view center on the disk so it cannot be panned away
+    fn clamp_center(&mut self) {
+        let r = self.center.0.hypot(self.center.1);
+        if r > 1.0 {
+            self.center.0 /= r;
+            self.center.1 /= r;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 200 x 100 view: 50 px per disk radius at zoom 1, centered at (100, 50)
+    const VIEW: (f64, f64) = (200.0, 100.0);
+
+    /// Viewer of a 4-pulse, 4-bin grid out to range 6 with value `10 * pulse + bin`
+    fn viewer() -> SweepViewer {
+        let bins: Vec<Vec<f32>> = (0..4).map(|p| (0..4).map(|b| (10 * p + b) as f32).collect()).collect();
+        let sweep = Sweep {
+            angles: vec![0.0, PI / 2.0, PI, 1.5 * PI],
+            bins: bins.clone(),
+            range_setting: 6,
+            gain: 60,
+            scale: None,
+            timestamp: "20240101120000".to_string(),
+            rotation: None,
+            heading: None,
+        };
+        let grid = PolarGrid { theta_edges: (0..=4).map(|p| p as f64 * PI / 2.0).collect(), bins };
+        SweepViewer::new(sweep, grid, &RenderOptions::default())
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn readout_at_a_known_pixel() {
+        let viewer = viewer();
+        // Half a radius east and south of the center: bearing 135°
+        let readout = viewer.readout(125.0, 75.0, VIEW.0, VIEW.1).unwrap();
+        assert_close(readout.range, 6.0 * 0.5f64.hypot(0.5));
+        assert_close(readout.bearing_deg, 135.0);
+        assert_eq!((readout.pulse, readout.bin), (1, 2));
+        assert_eq!(readout.value, 12.0);
+        // Beyond the disk
+        assert_eq!(viewer.readout(160.0, 50.0, VIEW.0, VIEW.1), None);
+    }
+
+    #[test]
+    fn zoom_keeps_the_point_under_the_cursor() {
+        let mut viewer = viewer();
+        let before = viewer.readout(130.0, 30.0, VIEW.0, VIEW.1).unwrap();
+        viewer.zoom_at(130.0, 30.0, 4.0, VIEW.0, VIEW.1);
+        assert_eq!(viewer.zoom(), 4.0);
+        let after = viewer.readout(130.0, 30.0, VIEW.0, VIEW.1).unwrap();
+        assert_close(after.range, before.range);
+        assert_close(after.bearing_deg, before.bearing_deg);
+        // The view center moved towards the cursor
+        assert_close(viewer.center.0, 0.6 * 0.75);
+        assert_close(viewer.center.1, 0.4 * 0.75);
+
+        viewer.zoom_at(130.0, 30.0, 1e6, VIEW.0, VIEW.1);
+        assert_eq!(viewer.zoom(), MAX_ZOOM);
+        viewer.zoom_at(130.0, 30.0, 1e-9, VIEW.0, VIEW.1);
+        assert_eq!(viewer.zoom(), MIN_ZOOM);
+    }
+
+    #[test]
+    fn pan_stays_on_the_disk() {
+        let mut viewer = viewer();
+        viewer.zoom_at(100.0, 50.0, 2.0, VIEW.0, VIEW.1);
+        // Dragging 50 px right at 100 px per radius shows what lies half a radius west
+        viewer.pan(50.0, 0.0, VIEW.0, VIEW.1);
+        assert_eq!(viewer.center, (-0.5, 0.0));
+        // Dragging far right and up stops at the south-west edge of the disk
+        viewer.reset();
+        viewer.pan(1e4, -1e4, VIEW.0, VIEW.1);
+        assert_close(viewer.center.0, -0.5f64.sqrt());
+        assert_close(viewer.center.1, -0.5f64.sqrt());
+        viewer.zoom_at(10.0, 10.0, 8.0, VIEW.0, VIEW.1);
+        viewer.reset();
+        assert_eq!((viewer.center, viewer.zoom()), ((0.0, 0.0), 1.0));
+    }
+}
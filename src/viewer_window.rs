@@ -0,0 +1,177 @@
+//! Sweep viewer window
+//!
+//! Shows a single CSV in a [`ViewerWindow`], rendered with the current
+//! settings and re-rendered by [`SweepViewer`] whenever the view is zoomed,
+//! panned or resized.
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use radar_plotter::viewer::{Readout, SweepViewer};
+use slint::{ComponentHandle, Rgba8Pixel, SharedPixelBuffer, SharedString};
+
+use crate::config;
+use crate::ViewerWindow;
+
+/// Create the viewer window and connect its callbacks.
+pub fn create() -> Result<ViewerWindow, slint::PlatformError> {
+    let window = ViewerWindow::new()?;
+    let viewer: Rc<RefCell<Option<SweepViewer>>> = Rc::new(RefCell::new(None));
+    window.set_readout_text("Open a CSV file to inspect a sweep".into());
+
+    {
+        let window_weak = window.as_weak();
+        let viewer = viewer.clone();
+        window.on_open_file(move || {
+            let window = window_weak.unwrap();
+            if let Some(path) = rfd::FileDialog::new()
+                .set_title("Select a CSV file to view")
+                .add_filter("CSV", &["csv", "CSV"])
+                .pick_file()
+            {
+                open(&window, &viewer, &path);
+            }
+        });
+    }
+
+    {
+        let window_weak = window.as_weak();
+        let viewer = viewer.clone();
+        window.on_reset_view(move || {
+            let window = window_weak.unwrap();
+            if let Some(viewer) = viewer.borrow_mut().as_mut() {
+                viewer.reset();
+                redraw(&window, viewer);
+            }
+        });
+    }
+
+    {
+        let window_weak = window.as_weak();
+        let viewer = viewer.clone();
+        window.on_view_resized(move || {
+            let window = window_weak.unwrap();
+            if let Some(viewer) = viewer.borrow().as_ref() {
+                redraw(&window, viewer);
+            }
+        });
+    }
+
+    {
+        let window_weak = window.as_weak();
+        let viewer = viewer.clone();
+        window.on_zoom_at(move |x, y, factor| {
+            let window = window_weak.unwrap();
+            if let Some(viewer) = viewer.borrow_mut().as_mut() {
+                let (width, height) = view_size(&window);
+                viewer.zoom_at(x as f64, y as f64, factor as f64, width, height);
+                redraw(&window, viewer);
+                show_readout(&window, viewer.readout(x as f64, y as f64, width, height));
+            }
+        });
+    }
+
+    {
+        let window_weak = window.as_weak();
+        let viewer = viewer.clone();
+        window.on_pan_by(move |dx, dy| {
+            let window = window_weak.unwrap();
+            if let Some(viewer) = viewer.borrow_mut().as_mut() {
+                let (width, height) = view_size(&window);
+                viewer.pan(dx as f64, dy as f64, width, height);
+                redraw(&window, viewer);
+            }
+        });
+    }
+
+    {
+        let window_weak = window.as_weak();
+        let viewer = viewer.clone();
+        window.on_hover(move |x, y| {
+            let window = window_weak.unwrap();
+            if let Some(viewer) = viewer.borrow().as_ref() {
+                let (width, height) = view_size(&window);
+                show_readout(&window, viewer.readout(x as f64, y as f64, width, height));
+            }
+        });
+    }
+
+    Ok(window)
+}
+
+/// Load `path` with the saved settings and show it
+fn open(window: &ViewerWindow, viewer: &RefCell<Option<SweepViewer>>, path: &Path) {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+
+    let opened = config::load_settings()
+        .unwrap_or_default()
+        .processing_settings()
+        .and_then(|settings| {
+            SweepViewer::open(
+                path,
+                &settings.csv_format,
+                &settings.grid_options(),
+                &settings.render_options(),
+            )
+        });
+    match opened {
+        Ok(opened) => {
+            let sweep = opened.sweep();
+            window.set_file_name(SharedString::from(format!(
+                "{}  (gain {}, range {})",
+                file_name, sweep.gain, sweep.range_setting
+            )));
+            window.set_readout_text("".into());
+            redraw(window, &opened);
+            *viewer.borrow_mut() = Some(opened);
+        }
+        Err(e) => {
+            window.set_file_name(file_name.into());
+            window.set_readout_text(SharedString::from(format!("Error: {:#}", e)));
+            window.set_view_image(slint::Image::default());
+            *viewer.borrow_mut() = None;
+        }
+    }
+}
+
+/// Logical size of the view area
+fn view_size(window: &ViewerWindow) -> (f64, f64) {
+    (window.get_view_width() as f64, window.get_view_height() as f64)
+}
+
+/// Render the view at the physical size of the view area
+fn redraw(window: &ViewerWindow, viewer: &SweepViewer) {
+    let scale = window.window().scale_factor();
+    let width = (window.get_view_width() * scale).round().max(1.0) as u32;
+    let height = (window.get_view_height() * scale).round().max(1.0) as u32;
+    let img = viewer.render(width, height);
+    let buffer = SharedPixelBuffer::<Rgba8Pixel>::clone_from_slice(img.as_raw(), width, height);
+    window.set_view_image(slint::Image::from_rgba8(buffer));
+
+    let grid = viewer.grid();
+    window.set_info_text(SharedString::from(format!(
+        "{} x {} grid  zoom {:.1}x",
+        grid.pulses(),
+        grid.n_bins(),
+        viewer.zoom()
+    )));
+}
+
+fn show_readout(window: &ViewerWindow, readout: Option<Readout>) {
+    let text = match readout {
+        Some(r) => {
+            let value = if r.value.is_finite() {
+                format!("{}", r.value)
+            } else {
+                "no data".to_string()
+            };
+            format!(
+                "Range {:.2}  Bearing {:.1}°  Value {}  Pulse {}  Bin {}",
+                r.range, r.bearing_deg, value, r.pulse, r.bin
+            )
+        }
+        None => String::new(),
+    };
+    window.set_readout_text(text.into());
+}
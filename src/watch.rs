@@ -0,0 +1,121 @@
+//! Detection of newly written CSV files for watch-folder mode
+//!
+//! Recorders write captures in place, so a file that has just appeared may
+//! still be growing. A file counts as complete once its size and modification
+//! time have stayed the same for a settle period, or when it was last
+//! modified longer ago than that. Files written under a temporary name and
+//! renamed to `.csv` when done are picked up on the first poll after the
+//! rename.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Default time between directory scans
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Default time a file must stay unchanged before it is rendered
+pub const DEFAULT_SETTLE: Duration = Duration::from_secs(2);
+
+/// Settings for watching folders after the initial batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchSettings {
+    pub poll_interval: Duration,
+    pub settle: Duration,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        WatchSettings {
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            settle: DEFAULT_SETTLE,
+        }
+    }
+}
+
+/// Size and modification time of a file when it last changed
+#[derive(Debug)]
+struct Observed {
+    size: u64,
+    modified: SystemTime,
+    since: Instant,
+}
+
+/// Tracks which files of a folder have been handed out for rendering.
+#[derive(Debug)]
+pub struct FileWatcher {
+    settle: Duration,
+    done: HashSet<PathBuf>,
+    pending: HashMap<PathBuf, Observed>,
+}
+
+impl FileWatcher {
+    pub fn new(settle: Duration) -> Self {
+        FileWatcher {
+            settle,
+            done: HashSet::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// The files of `files` that are complete and were not returned before,
+    /// in their original order.
+    ///
+    /// Files that are still being written are remembered and returned by a
+    /// later call once they have settled.
+    pub fn ready(&mut self, files: Vec<PathBuf>) -> Vec<PathBuf> {
+        let now = SystemTime::now();
+        let mut ready = Vec::new();
+        for path in files {
+            if self.done.contains(&path) {
+                continue;
+            }
+            let Ok(meta) = fs::metadata(&path) else {
+                continue;
+            };
+            let size = meta.len();
+            let modified = meta.modified().unwrap_or(now);
+            let age = now.duration_since(modified).unwrap_or_default();
+
+            let settled = match self.pending.get(&path) {
+                Some(seen) if seen.size == size && seen.modified == modified => seen.since.elapsed() >= self.settle,
+                _ => {
+                    self.pending.insert(path.clone(), Observed { size, modified, since: Instant::now() });
+                    false
+                }
+            };
+            if size > 0 && (settled || age >= self.settle) {
+                self.pending.remove(&path);
+                self.done.insert(path.clone());
+                ready.push(path);
+            }
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_returned_once_settled() {
+        let dir = std::env::temp_dir().join(format!("radar_plotter_watch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let [old, empty, fresh] = ["old.csv", "empty.csv", "fresh.csv"].map(|name| dir.join(name));
+        fs::write(&old, "Range,Gain,Angle,EchoValues\n").unwrap();
+        fs::write(&empty, "").unwrap();
+        fs::write(&fresh, "Range,Gain,Angle,EchoValues\n").unwrap();
+        let hours_ago = SystemTime::now() - Duration::from_secs(7200);
+        for path in [&old, &empty] {
+            fs::File::options().write(true).open(path).unwrap().set_modified(hours_ago).unwrap();
+        }
+
+        let mut watcher = FileWatcher::new(Duration::from_secs(3600));
+        let files = vec![fresh.clone(), old.clone(), empty.clone(), dir.join("missing.csv")];
+        // Only the file last written longer ago than the settle period is complete
+        assert_eq!(watcher.ready(files.clone()), [old]);
+        assert!(watcher.ready(files).is_empty());
+        assert!(watcher.pending.contains_key(&fresh));
+    }
+}